@@ -0,0 +1,109 @@
+use rusty::chess::Color;
+use rusty::{read_pgn, to_san, write_pgn, Engine, SearchLimits};
+
+// evaluates every move of the main lines of the games in a PGN file against the engine's choice;
+// with `output` the games are also written there, annotated with the evaluations
+pub(crate) fn analyze(
+    path: &str,
+    limits: SearchLimits,
+    output: Option<&str>,
+    engine: &mut Engine,
+) -> Result<(), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut annotated = Vec::new();
+    for (number, game) in read_pgn(&text).into_iter().enumerate() {
+        let mut game = match game {
+            Ok(game) => game,
+            Err(error) => {
+                eprintln!("game {}: {}, skipped", number + 1, error);
+                continue;
+            }
+        };
+        println!("game {}", number + 1);
+        engine.set_position(*game.start());
+        let mut best = engine.search(limits);
+        for node in game.mainline() {
+            let before = game.nodes[game.nodes[node].parent.unwrap_or(0)].position;
+            let Some(m) = game.nodes[node].m else {
+                continue;
+            };
+            let mover = before.board.side_to_move();
+            let label = match mover {
+                Color::White => format!("{}.", before.fullmove_number),
+                Color::Black => format!("{}...", before.fullmove_number),
+            };
+            engine.set_position(game.nodes[node].position);
+            let next = engine.search(limits);
+            // both scores from white's point of view
+            let played = next.score.for_side(!mover);
+            game.nodes[node].eval = Some(played);
+            let mut line = format!(
+                "{:>5} {:<8} {:>7}",
+                label,
+                to_san(&before.board, m),
+                played.to_string()
+            );
+            if let Some(best_move) = best.best_move.filter(|&b| b != m) {
+                let score = best.score.for_side(mover);
+                line.push_str(&format!(
+                    "   best {} ({})",
+                    to_san(&before.board, best_move),
+                    score
+                ));
+            }
+            println!("{}", line.trim_end());
+            best = next;
+        }
+        annotated.push(write_pgn(&game));
+    }
+    match output {
+        Some(output) => {
+            std::fs::write(output, annotated.join("\n")).map_err(|e| format!("{}: {}", output, e))
+        }
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusty::EngineConfig;
+
+    #[test]
+    fn annotates_the_games() {
+        let directory = std::env::temp_dir();
+        let input = directory.join(format!("rusty-analyze-{}.pgn", std::process::id()));
+        let output = directory.join(format!("rusty-analyzed-{}.pgn", std::process::id()));
+        let games = "[White \"A\"]\n1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0\n\n\
+                     [White \"B\"]\n1. e4 Ke7 2. Ke3 *\n\n\
+                     [White \"C\"]\n1. d4 (1. e4) d5 *\n";
+        std::fs::write(&input, games).unwrap();
+        let limits = SearchLimits {
+            depth: Some(2),
+            ..Default::default()
+        };
+        let mut engine = Engine::new(EngineConfig::default());
+        let result = analyze(
+            input.to_str().unwrap(),
+            limits,
+            Some(output.to_str().unwrap()),
+            &mut engine,
+        );
+        let written = std::fs::read_to_string(&output).unwrap();
+        std::fs::remove_file(&input).ok();
+        std::fs::remove_file(&output).ok();
+        result.unwrap();
+        let movetext = written.split_whitespace().collect::<Vec<_>>().join(" ");
+        // the game with an illegal move is left out
+        let games: Vec<_> = read_pgn(&written).into_iter().map(Result::unwrap).collect();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("White"), Some("A"));
+        assert!(movetext.contains("4. Qxf7# {[%eval #0]} 1-0"));
+        let main = games[1].mainline();
+        assert!(main.iter().all(|&n| games[1].nodes[n].eval.is_some()));
+        // variations are kept, without evaluations
+        assert_eq!(games[1].nodes[0].children.len(), 2);
+        assert!(movetext.contains("1. d4 {[%eval"));
+        assert!(movetext.contains("(1. e4)"));
+    }
+}
@@ -0,0 +1,559 @@
+use chess::{Board, BoardStatus, ChessMove};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::engine::{evaluate, time_for_another_iteration, NoTrace, Searcher};
+use crate::mate::MateSearcher;
+use crate::nnue::Network;
+use crate::position::Position;
+use crate::score::Score;
+use crate::weights::EvalParams;
+
+// The public face of the engine. Everything else in the crate is free to change; these types
+// are what other programs build on.
+
+/// Search used when no limit is given.
+pub const DEFAULT_MOVETIME: Duration = Duration::from_secs(1);
+
+// nodes the mate search may use when no node limit is given
+const MATE_NODES: u64 = 20_000_000;
+
+/// How the engine evaluates positions.
+#[derive(Clone, Default)]
+pub struct EngineConfig {
+    /// Weights of the hand-crafted evaluation.
+    pub params: EvalParams,
+    /// Evaluates with this network instead of the weights when set.
+    pub network: Option<Arc<Network>>,
+}
+
+/// Limits for [`Engine::search`]. Only the first limit that is set counts, in the order
+/// `mate`, `infinite`, `depth`, `nodes`, `movetime`, except that `depth` and `movetime` apply
+/// together; with none set the engine searches for [`DEFAULT_MOVETIME`]. Any search ends early
+/// through [`Engine::stop_flag`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchLimits {
+    /// Look for a forced mate in at most this many moves first, falling back to a normal
+    /// search when there is none. The mate search gives up at `nodes`, or 20 million nodes
+    /// without a node limit, and at the stop flag.
+    pub mate: Option<u8>,
+    /// Lets the mate search try only checking moves for the side to mate, which finds long
+    /// mates by checks sooner but misses mates that start with a quiet move.
+    pub mate_checks_only: bool,
+    /// Deepen until stopped.
+    pub infinite: bool,
+    /// Deepen up to this depth in plies, or until a mate is found.
+    pub depth: Option<u8>,
+    /// Deepen until at least this many nodes have been searched or a mate is found.
+    pub nodes: Option<u64>,
+    /// Deepen while another iteration is expected to finish within this time, or until a
+    /// mate is found. An iteration still running when the time is up is cut short.
+    pub movetime: Option<Duration>,
+}
+
+/// The outcome of a search. It describes the last completed iteration; an iteration cut short
+/// by a stop is discarded.
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    /// The move to play, `None` when the game is already over.
+    pub best_move: Option<ChessMove>,
+    /// From the side to move's point of view.
+    pub score: Score,
+    /// The expected continuation, starting with the best move.
+    pub pv: Vec<ChessMove>,
+    /// Depth of the last completed iteration in plies.
+    pub depth: u8,
+    /// The deepest ply that iteration reached.
+    pub seldepth: u8,
+    /// Nodes searched in all iterations, including an interrupted one.
+    pub nodes: u64,
+    pub elapsed: Duration,
+    /// Whether the stop flag ended the search before its limit.
+    pub stopped: bool,
+}
+
+/// Progress of a search after a completed iteration.
+#[derive(Clone, Debug)]
+pub struct IterationInfo {
+    pub depth: u8,
+    pub seldepth: u8,
+    /// From the side to move's point of view.
+    pub score: Score,
+    pub pv: Vec<ChessMove>,
+    /// Nodes searched so far, over all iterations.
+    pub nodes: u64,
+    /// Time since the search started.
+    pub elapsed: Duration,
+}
+
+/// Receives progress while [`Engine::search_with`] runs. Both methods do nothing by default,
+/// and `()` is an observer that ignores everything.
+pub trait SearchObserver {
+    /// Called after every completed iteration of the deepening.
+    fn iteration(&mut self, _info: &IterationInfo) {}
+
+    /// Called before each root move is searched, with its 1-based number in the move list and
+    /// the depth of the iteration.
+    fn current_move(&mut self, _m: ChessMove, _number: usize, _depth: u8) {}
+}
+
+impl SearchObserver for () {}
+
+/// A search event as sent to a channel.
+#[derive(Clone, Debug)]
+pub enum SearchEvent {
+    Iteration(IterationInfo),
+    CurrentMove {
+        m: ChessMove,
+        number: usize,
+        depth: u8,
+    },
+}
+
+/// Sends every event to the channel, so another thread can follow the search. Events are
+/// dropped once the receiver is gone.
+impl SearchObserver for mpsc::Sender<SearchEvent> {
+    fn iteration(&mut self, info: &IterationInfo) {
+        let _ = self.send(SearchEvent::Iteration(info.clone()));
+    }
+
+    fn current_move(&mut self, m: ChessMove, number: usize, depth: u8) {
+        let _ = self.send(SearchEvent::CurrentMove { m, number, depth });
+    }
+}
+
+/// A chess engine holding a position to search.
+///
+/// ```
+/// use rusty::{Engine, EngineConfig, Position, SearchLimits};
+///
+/// let mut engine = Engine::new(EngineConfig::default());
+/// engine.set_position(Position::default());
+/// let result = engine.search(SearchLimits {
+///     depth: Some(2),
+///     ..Default::default()
+/// });
+/// assert!(result.best_move.is_some());
+/// ```
+pub struct Engine {
+    position: Position,
+    params: Arc<EvalParams>,
+    network: Option<Arc<Network>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Engine {
+    pub fn new(config: EngineConfig) -> Engine {
+        Engine {
+            position: Position::default(),
+            params: Arc::new(config.params),
+            network: config.network,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn set_position(&mut self, position: Position) {
+        self.position = position;
+    }
+
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+
+    pub fn set_params(&mut self, params: EvalParams) {
+        self.params = Arc::new(params);
+    }
+
+    pub fn params(&self) -> &EvalParams {
+        &self.params
+    }
+
+    /// Switches to the network, or back to the weights with `None`.
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.network = network;
+    }
+
+    pub fn network(&self) -> Option<&Network> {
+        self.network.as_deref()
+    }
+
+    /// Setting this flag from another thread ends the running search, which then returns the
+    /// last completed iteration. The search clears it when it returns.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// Searches the current position.
+    pub fn search(&mut self, limits: SearchLimits) -> SearchResult {
+        self.search_with(limits, &mut ())
+    }
+
+    /// Searches the current position, reporting progress to `observer`.
+    pub fn search_with(
+        &mut self,
+        limits: SearchLimits,
+        observer: &mut dyn SearchObserver,
+    ) -> SearchResult {
+        let result = self.run(limits, observer);
+        self.stop.store(false, Ordering::Relaxed);
+        result
+    }
+
+    fn run(&mut self, limits: SearchLimits, observer: &mut dyn SearchObserver) -> SearchResult {
+        let start = Instant::now();
+        let board = self.position.board;
+        if board.status() != BoardStatus::Ongoing {
+            return SearchResult {
+                best_move: None,
+                score: self.evaluate(&board),
+                pv: Vec::new(),
+                depth: 0,
+                seldepth: 0,
+                nodes: 0,
+                elapsed: start.elapsed(),
+                stopped: false,
+            };
+        }
+
+        let mut nodes = 0;
+        if let Some(moves) = limits.mate {
+            let mut searcher = MateSearcher::new(&board, moves);
+            searcher.checks_only = limits.mate_checks_only;
+            searcher.max_nodes = limits.nodes.unwrap_or(MATE_NODES);
+            searcher.stop = Some(self.stop.clone());
+            let line = searcher.search();
+            nodes = searcher.nodes;
+            if let Some(line) = line {
+                let depth = line.len() as u8;
+                let score = Score::mate_in_plies(line.len() as i32);
+                observer.iteration(&IterationInfo {
+                    depth,
+                    seldepth: depth,
+                    score,
+                    pv: line.clone(),
+                    nodes,
+                    elapsed: start.elapsed(),
+                });
+                return SearchResult {
+                    best_move: Some(line[0]),
+                    score,
+                    pv: line,
+                    depth,
+                    seldepth: depth,
+                    nodes,
+                    elapsed: start.elapsed(),
+                    stopped: false,
+                };
+            }
+        }
+
+        let mut searcher = Searcher::from_position(&self.position, 1);
+        searcher.params = self.params.clone();
+        searcher.network = self.network.clone();
+        searcher.stop = Some(self.stop.clone());
+        // the time of a timed search is also a hard limit, for the iteration that takes much
+        // longer than expected
+        let deadline = if limits.infinite {
+            None
+        } else if limits.depth.is_some() {
+            limits.movetime
+        } else if limits.nodes.is_some() {
+            None
+        } else {
+            Some(limits.movetime.unwrap_or(DEFAULT_MOVETIME))
+        };
+        let (score, expired) = with_deadline(&self.stop, deadline, || {
+            if limits.infinite {
+                searcher.iterate(observer, |_, _| true)
+            } else if let Some(depth) = limits.depth {
+                // a movetime next to the depth still bounds the search, so a depth cap cannot
+                // make a timed search lose on time
+                let time = limits.movetime;
+                searcher.iterate(observer, |searcher, elapsed| {
+                    searcher.depth < depth
+                        && !searcher.best_score.is_mate()
+                        && time.is_none_or(|time| time_for_another_iteration(elapsed, time))
+                })
+            } else if let Some(limit) = limits.nodes {
+                searcher.iterate(observer, |searcher, _| searcher.nodes < limit)
+            } else {
+                let time = limits.movetime.unwrap_or(DEFAULT_MOVETIME);
+                // the first iteration that sees a mate finds the shortest one, so a mate ends
+                // the search
+                searcher.iterate(observer, |searcher, elapsed| {
+                    !searcher.best_score.is_mate() && time_for_another_iteration(elapsed, time)
+                })
+            }
+        });
+        let pv = searcher.pv();
+        SearchResult {
+            best_move: pv.first().copied(),
+            score,
+            pv,
+            depth: searcher.depth,
+            seldepth: searcher.seldepth,
+            nodes: nodes + searcher.nodes,
+            elapsed: start.elapsed(),
+            // running out of time is the limit, not a stop
+            stopped: searcher.stopped && !expired,
+        }
+    }
+
+    /// Static evaluation of `board` with the configured network or weights, from the side to
+    /// move's point of view.
+    pub fn evaluate(&self, board: &Board) -> Score {
+        let score = match &self.network {
+            Some(network) if board.status() == BoardStatus::Ongoing => {
+                Score::cp(network.evaluate_board(board))
+            }
+            _ => evaluate(board, &self.params, &mut NoTrace),
+        };
+        score.for_side(board.side_to_move())
+    }
+}
+
+fn search_board(board: &Board, limits: SearchLimits) -> SearchResult {
+    let mut engine = Engine::new(EngineConfig::default());
+    engine.set_position(Position::from(*board));
+    engine.search(limits)
+}
+
+/// Searches `board` to `depth` with the default evaluation.
+pub fn best_move(board: &Board, depth: u8) -> SearchResult {
+    search_board(
+        board,
+        SearchLimits {
+            depth: Some(depth),
+            ..Default::default()
+        },
+    )
+}
+
+/// Searches `board` for about `time` milliseconds with the default evaluation.
+pub fn best_move_with_time(board: &Board, time: u64) -> SearchResult {
+    search_board(
+        board,
+        SearchLimits {
+            movetime: Some(Duration::from_millis(time)),
+            ..Default::default()
+        },
+    )
+}
+
+/// Searches `board` until `true` arrives on `receiver` or the sender hangs up.
+pub fn best_move_infinite_thread(board: &Board, receiver: mpsc::Receiver<bool>) -> SearchResult {
+    let mut engine = Engine::new(EngineConfig::default());
+    engine.set_position(Position::from(*board));
+    let stop = engine.stop_flag();
+    let watcher = thread::spawn(move || {
+        while let Ok(message) = receiver.recv() {
+            if message {
+                break;
+            }
+        }
+        stop.store(true, Ordering::Relaxed);
+    });
+    let result = engine.search(SearchLimits {
+        infinite: true,
+        ..Default::default()
+    });
+    let _ = watcher.join();
+    result
+}
+// runs `search` while a timer sets the stop flag once `limit` has passed, and tells whether it
+// did; the timer is called off when the search ends first
+fn with_deadline<T>(
+    stop: &Arc<AtomicBool>,
+    limit: Option<Duration>,
+    search: impl FnOnce() -> T,
+) -> (T, bool) {
+    let Some(limit) = limit else {
+        return (search(), false);
+    };
+    let (done, finished) = mpsc::channel::<()>();
+    let stop = stop.clone();
+    let timer = thread::spawn(move || {
+        let expired = finished.recv_timeout(limit) == Err(mpsc::RecvTimeoutError::Timeout);
+        if expired {
+            stop.store(true, Ordering::Relaxed);
+        }
+        expired
+    });
+    let result = search();
+    drop(done);
+    (result, timer.join().expect("the search timer panicked"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn search(fen: &str, limits: SearchLimits) -> SearchResult {
+        let mut engine = Engine::new(EngineConfig::default());
+        engine.set_position(Position::from_fen(fen).unwrap());
+        engine.search(limits)
+    }
+
+    #[test]
+    fn pv_starts_with_the_best_move() {
+        let result = search(
+            "r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 3 3",
+            SearchLimits {
+                depth: Some(3),
+                ..Default::default()
+            },
+        );
+        assert_eq!(result.depth, 3);
+        assert_eq!(result.pv.len(), 3);
+        assert_eq!(result.best_move, result.pv.first().copied());
+        assert!(result.nodes > 0);
+    }
+
+    #[test]
+    fn mate_search_finds_the_shortest_mate() {
+        let limits = SearchLimits {
+            mate: Some(3),
+            ..Default::default()
+        };
+        let result = search("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", limits);
+        assert_eq!(result.pv, vec![ChessMove::from_str("a1a8").unwrap()]);
+        assert_eq!(result.score.mate_plies(), Some(1));
+        let result = search("r5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1", limits);
+        assert_eq!(result.pv, vec![ChessMove::from_str("a8a1").unwrap()]);
+        assert_eq!(result.score.mate_in(), Some(1));
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        depths: Vec<u8>,
+        root_moves: Vec<(u8, usize)>,
+    }
+
+    impl SearchObserver for Recorder {
+        fn iteration(&mut self, info: &IterationInfo) {
+            assert_eq!(info.pv.len(), info.depth as usize);
+            self.depths.push(info.depth);
+        }
+
+        fn current_move(&mut self, _m: ChessMove, number: usize, depth: u8) {
+            self.root_moves.push((depth, number));
+        }
+    }
+
+    #[test]
+    fn observer_sees_every_iteration_and_root_move() {
+        let mut engine = Engine::new(EngineConfig::default());
+        let mut recorder = Recorder::default();
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..Default::default()
+        };
+        engine.search_with(limits, &mut recorder);
+        assert_eq!(recorder.depths, vec![1, 2, 3]);
+        let first: Vec<usize> = recorder
+            .root_moves
+            .iter()
+            .filter(|(depth, _)| *depth == 1)
+            .map(|(_, number)| *number)
+            .collect();
+        assert_eq!(first, (1..=20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn events_arrive_over_a_channel() {
+        let mut engine = Engine::new(EngineConfig::default());
+        let (mut sender, receiver) = mpsc::channel();
+        let limits = SearchLimits {
+            depth: Some(2),
+            ..Default::default()
+        };
+        let result = engine.search_with(limits, &mut sender);
+        drop(sender);
+        let last = receiver
+            .iter()
+            .filter_map(|event| match event {
+                SearchEvent::Iteration(info) => Some(info),
+                SearchEvent::CurrentMove { .. } => None,
+            })
+            .last()
+            .unwrap();
+        assert_eq!(last.depth, 2);
+        assert_eq!(last.pv, result.pv);
+        assert_eq!(last.nodes, result.nodes);
+    }
+
+    #[test]
+    fn normal_search_reports_mate_for_the_side_to_move() {
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..Default::default()
+        };
+        let result = search("r5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1", limits);
+        assert_eq!(result.score.mate_in(), Some(1));
+        assert_eq!(result.seldepth, 1);
+        assert!(!result.stopped);
+        // white's only move is Kg1, after which Ra1 mates
+        let result = search("7k/8/8/8/8/r7/1r6/7K w - - 0 1", limits);
+        assert_eq!(result.score.mate_plies(), Some(-2));
+        assert_eq!(result.score.mate_in(), Some(-1));
+    }
+
+    #[test]
+    fn deadline_stops_a_search_that_runs_over() {
+        // a search that only ends when stopped
+        let stop = Arc::new(AtomicBool::new(false));
+        let start = Instant::now();
+        let ((), expired) = with_deadline(&stop, Some(Duration::from_millis(50)), || {
+            while !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+        });
+        assert!(expired);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        // a search that ends in time calls the timer off at once
+        let stop = Arc::new(AtomicBool::new(false));
+        let start = Instant::now();
+        let ((), expired) = with_deadline(&stop, Some(Duration::from_secs(60)), || {});
+        assert!(!expired);
+        assert!(!stop.load(Ordering::Relaxed));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn timed_search_ends_at_a_mate() {
+        let limits = SearchLimits {
+            movetime: Some(Duration::from_secs(30)),
+            ..Default::default()
+        };
+        let result = search("6k1/5ppp/8/8/8/8/8/R3R1K1 w - - 0 1", limits);
+        assert_eq!(result.score.mate_in(), Some(1));
+        assert_eq!(result.depth, 1);
+        assert!(result.elapsed < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn stopped_search_keeps_the_last_iteration() {
+        let (sender, receiver) = mpsc::channel();
+        let search = thread::spawn(move || best_move_infinite_thread(&Board::default(), receiver));
+        thread::sleep(Duration::from_millis(300));
+        sender.send(true).unwrap();
+        let result = search.join().unwrap();
+        assert!(result.stopped);
+        assert!(result.depth >= 1);
+        assert_eq!(result.pv.len(), result.depth as usize);
+    }
+
+    #[test]
+    fn finished_game_has_no_move() {
+        let result = search(
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+            SearchLimits::default(),
+        );
+        assert_eq!(result.best_move, None);
+        assert!(result.pv.is_empty());
+        assert!(result.score.is_mate());
+    }
+}
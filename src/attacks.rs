@@ -0,0 +1,59 @@
+use chess::{
+    get_bishop_moves, get_king_moves, get_knight_moves, get_pawn_attacks, get_rook_moves, BitBoard,
+    Board, Color, Piece, Square, EMPTY,
+};
+
+// The squares each side attacks, built once per evaluation and shared by the terms that need
+// them.
+
+// squares attacked by a piece standing on `square`
+pub(crate) fn piece_attacks(
+    piece: Piece,
+    square: Square,
+    color: Color,
+    occupied: BitBoard,
+) -> BitBoard {
+    match piece {
+        Piece::Pawn => get_pawn_attacks(square, color, !EMPTY),
+        Piece::Knight => get_knight_moves(square),
+        Piece::Bishop => get_bishop_moves(square, occupied),
+        Piece::Rook => get_rook_moves(square, occupied),
+        Piece::Queen => get_bishop_moves(square, occupied) | get_rook_moves(square, occupied),
+        Piece::King => get_king_moves(square),
+    }
+}
+
+pub(crate) struct AttackMaps {
+    // by color, then by piece
+    pub(crate) by_piece: [[BitBoard; 6]; 2],
+    // everything a side attacks
+    pub(crate) all: [BitBoard; 2],
+}
+
+impl AttackMaps {
+    pub(crate) fn new(board: &Board) -> AttackMaps {
+        let mut maps = AttackMaps {
+            by_piece: [[EMPTY; 6]; 2],
+            all: [EMPTY; 2],
+        };
+        for color in [Color::White, Color::Black] {
+            let c = color.to_index();
+            for piece in chess::ALL_PIECES {
+                for square in *board.pieces(piece) & *board.color_combined(color) {
+                    maps.by_piece[c][piece.to_index()] |=
+                        piece_attacks(piece, square, color, *board.combined());
+                }
+                maps.all[c] |= maps.by_piece[c][piece.to_index()];
+            }
+        }
+        maps
+    }
+
+    pub(crate) fn pieces(&self, color: Color, piece: Piece) -> BitBoard {
+        self.by_piece[color.to_index()][piece.to_index()]
+    }
+
+    pub(crate) fn side(&self, color: Color) -> BitBoard {
+        self.all[color.to_index()]
+    }
+}
@@ -0,0 +1,395 @@
+use rusty::tools::{DatagenOptions, BENCH_DEPTH};
+use rusty::{Position, SearchLimits};
+use std::str::FromStr;
+use std::time::Duration;
+
+// the command line: global options, then one subcommand with its own arguments
+
+pub(crate) const USAGE: &str = "\
+usage: rusty [--eval-file <file>] [--nnue <file>] [command]
+
+commands:
+  (none)       speak UCI or XBoard, whichever the first line on stdin asks for
+  uci          speak UCI
+  xboard       speak XBoard (CECP)
+  play         play a game in the terminal
+               [--black] [--depth n | --movetime ms] [--unicode] [--fen <fen>]
+  search       search one position and print the best move
+               [--fen <fen>] [--depth n | --movetime ms | --nodes n]
+  eval         print the evaluation of a position [--fen <fen>]
+  analyze      evaluate every move of the games in a PGN file
+               <file> [--depth n | --movetime ms | --nodes n] [--output <file>]
+               --output writes the games with the evaluations as [%eval] comments
+  bench        search the benchmark positions [depth]
+  perft        count the leaf nodes of the move tree <depth> [--fen <fen>]
+  divide       perft split by the first move <depth> [--fen <fen>]
+  tune         fit the evaluation weights to a data file
+               <data file> [iterations] [output]
+  datagen      write training positions from self-play <games> <output>
+               [--threads n] [--nodes n] [--seed n] [--random-plies n]
+               [--format text|binary]
+               --nodes is a soft limit per move: the iteration that passes it finishes
+  help         show this text
+
+global options:
+  --eval-file  evaluation weights written by tune
+  --nnue       evaluate with a network instead of the weights
+
+fens are read up to the next option, so they need no quotes";
+
+// per-move limit of search and analyze when none is given
+const DEFAULT_DEPTH: u8 = 8;
+
+pub(crate) enum Command {
+    // choose the protocol from the first input line
+    Protocol,
+    Uci,
+    Xboard,
+    // the play options are parsed by the play module itself
+    Play(Vec<String>),
+    Search {
+        position: Position,
+        limits: SearchLimits,
+    },
+    Eval(Position),
+    Analyze {
+        path: String,
+        limits: SearchLimits,
+        output: Option<String>,
+    },
+    Bench(u8),
+    Perft {
+        depth: u8,
+        position: Position,
+        divide: bool,
+    },
+    Tune {
+        path: String,
+        iterations: usize,
+        output: String,
+    },
+    Datagen {
+        options: DatagenOptions,
+        output: String,
+    },
+    Help,
+}
+
+pub(crate) struct Cli {
+    pub(crate) weights: Option<String>,
+    pub(crate) nnue: Option<String>,
+    pub(crate) command: Command,
+}
+
+// `args` without the program name; errors are usage errors
+pub(crate) fn parse(mut args: Vec<String>) -> Result<Cli, String> {
+    let weights = take_value(&mut args, "--eval-file")?;
+    let nnue = take_value(&mut args, "--nnue")?;
+    if args.is_empty() {
+        return Ok(Cli {
+            weights,
+            nnue,
+            command: Command::Protocol,
+        });
+    }
+    let name = args.remove(0);
+    let command = match name.as_str() {
+        "uci" => no_arguments(args, Command::Uci)?,
+        "xboard" => no_arguments(args, Command::Xboard)?,
+        "help" | "--help" | "-h" => Command::Help,
+        "play" => Command::Play(args),
+        "search" => {
+            let position = take_fen(&mut args)?;
+            let limits = take_limits(&mut args)?;
+            no_arguments(args, Command::Search { position, limits })?
+        }
+        "eval" => {
+            let position = take_fen(&mut args)?;
+            no_arguments(args, Command::Eval(position))?
+        }
+        "analyze" => {
+            let limits = take_limits(&mut args)?;
+            let output = take_value(&mut args, "--output")?;
+            match positional(args)?.as_slice() {
+                [path] => Command::Analyze {
+                    path: path.clone(),
+                    limits,
+                    output,
+                },
+                _ => return Err(String::from("analyze needs one PGN file")),
+            }
+        }
+        "bench" => match positional(args)?.as_slice() {
+            [] => Command::Bench(BENCH_DEPTH),
+            [depth] => Command::Bench(number(depth, "depth").and_then(nonzero)?),
+            _ => return Err(String::from("bench takes at most a depth")),
+        },
+        "perft" | "divide" => {
+            let position = take_fen(&mut args)?;
+            match positional(args)?.as_slice() {
+                [depth] => Command::Perft {
+                    depth: number(depth, "depth")?,
+                    position,
+                    divide: name == "divide",
+                },
+                _ => return Err(format!("{} needs a depth", name)),
+            }
+        }
+        "tune" => {
+            let (path, iterations, output) = match positional(args)?.as_slice() {
+                [path] => (path.clone(), 1000, String::from("weights.txt")),
+                [path, iterations] => (
+                    path.clone(),
+                    number(iterations, "iteration count")?,
+                    String::from("weights.txt"),
+                ),
+                [path, iterations, output] => (
+                    path.clone(),
+                    number(iterations, "iteration count")?,
+                    output.clone(),
+                ),
+                _ => return Err(String::from("tune needs a data file")),
+            };
+            Command::Tune {
+                path,
+                iterations: nonzero(iterations)?,
+                output,
+            }
+        }
+        "datagen" => {
+            let threads = take_number(&mut args, "--threads")?.unwrap_or(1);
+            let nodes = take_number(&mut args, "--nodes")?.unwrap_or(5000);
+            let seed = take_number(&mut args, "--seed")?.unwrap_or(0);
+            let random_plies = take_number(&mut args, "--random-plies")?.unwrap_or(8);
+            let binary = match take_value(&mut args, "--format")?.as_deref() {
+                None | Some("text") => false,
+                Some("binary") => true,
+                Some(format) => return Err(format!("unknown format {}", format)),
+            };
+            match positional(args)?.as_slice() {
+                [games, output] => Command::Datagen {
+                    options: DatagenOptions {
+                        games: number(games, "game count")?,
+                        threads: threads.max(1),
+                        nodes,
+                        seed,
+                        random_plies,
+                        binary,
+                    },
+                    output: output.clone(),
+                },
+                _ => {
+                    return Err(String::from(
+                        "datagen needs a game count and an output file",
+                    ))
+                }
+            }
+        }
+        _ => return Err(format!("unknown command {}", name)),
+    };
+    Ok(Cli {
+        weights,
+        nnue,
+        command,
+    })
+}
+
+fn number<T: FromStr>(text: &str, what: &str) -> Result<T, String> {
+    text.parse()
+        .map_err(|_| format!("invalid {}: {}", what, text))
+}
+
+fn nonzero<T: Default + PartialEq>(value: T) -> Result<T, String> {
+    if value == T::default() {
+        Err(String::from("a count of 0 does nothing"))
+    } else {
+        Ok(value)
+    }
+}
+
+// removes `<flag> <value>` from the arguments and returns the value
+fn take_value(args: &mut Vec<String>, flag: &str) -> Result<Option<String>, String> {
+    let Some(i) = args.iter().position(|a| a == flag) else {
+        return Ok(None);
+    };
+    if i + 1 >= args.len() {
+        return Err(format!("{} needs a value", flag));
+    }
+    let value = args.remove(i + 1);
+    args.remove(i);
+    Ok(Some(value))
+}
+
+fn take_number<T: FromStr>(args: &mut Vec<String>, flag: &str) -> Result<Option<T>, String> {
+    take_value(args, flag)?
+        .map(|value| number(&value, &flag[2..]))
+        .transpose()
+}
+
+// `--fen` and every word after it up to the next option; the start position without one
+fn take_fen(args: &mut Vec<String>) -> Result<Position, String> {
+    let Some(i) = args.iter().position(|a| a == "--fen") else {
+        return Ok(Position::default());
+    };
+    let end = (i + 1..args.len())
+        .find(|&j| args[j].starts_with("--"))
+        .unwrap_or(args.len());
+    let fen: Vec<String> = args.drain(i..end).skip(1).collect();
+    if fen.is_empty() {
+        return Err(String::from("--fen needs a position"));
+    }
+    Position::from_fen(&fen.join(" ")).map_err(|e| format!("invalid fen: {}", e))
+}
+
+// at most one of --depth, --movetime (milliseconds) and --nodes
+fn take_limits(args: &mut Vec<String>) -> Result<SearchLimits, String> {
+    let depth = take_number::<u8>(args, "--depth")?
+        .map(nonzero)
+        .transpose()?;
+    let movetime = take_number::<u64>(args, "--movetime")?
+        .map(nonzero)
+        .transpose()?;
+    let nodes = take_number::<u64>(args, "--nodes")?
+        .map(nonzero)
+        .transpose()?;
+    let given = [depth.is_some(), movetime.is_some(), nodes.is_some()];
+    if given.iter().filter(|&&g| g).count() > 1 {
+        return Err(String::from(
+            "give only one of --depth, --movetime and --nodes",
+        ));
+    }
+    Ok(SearchLimits {
+        depth: depth
+            .or(Some(DEFAULT_DEPTH))
+            .filter(|_| movetime.is_none() && nodes.is_none()),
+        movetime: movetime.map(Duration::from_millis),
+        nodes,
+        ..Default::default()
+    })
+}
+
+// the arguments left once the options are taken out, which must not look like options
+fn positional(args: Vec<String>) -> Result<Vec<String>, String> {
+    match args.iter().find(|a| a.starts_with("--")) {
+        Some(option) => Err(format!("unknown option {}", option)),
+        None => Ok(args),
+    }
+}
+
+fn no_arguments(args: Vec<String>, command: Command) -> Result<Command, String> {
+    match positional(args)?.first() {
+        Some(extra) => Err(format!("unexpected argument {}", extra)),
+        None => Ok(command),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_line(line: &str) -> Result<Cli, String> {
+        parse(line.split_whitespace().map(String::from).collect())
+    }
+
+    fn command(line: &str) -> Command {
+        parse_line(line)
+            .unwrap_or_else(|e| panic!("{}: {}", line, e))
+            .command
+    }
+
+    #[test]
+    fn defaults_to_the_protocols() {
+        assert!(matches!(command(""), Command::Protocol));
+        assert!(matches!(command("uci"), Command::Uci));
+        let cli = parse_line("--nnue net.bin --eval-file w.txt").unwrap();
+        assert!(matches!(cli.command, Command::Protocol));
+        assert_eq!(cli.nnue.as_deref(), Some("net.bin"));
+        assert_eq!(cli.weights.as_deref(), Some("w.txt"));
+        assert!(matches!(command("--help"), Command::Help));
+    }
+
+    #[test]
+    fn fens_run_to_the_next_option() {
+        let fen = "8/8/8/8/8/8/k7/4K2R w K - 0 1";
+        match command(&format!("search --fen {} --movetime 250", fen)) {
+            Command::Search { position, limits } => {
+                assert_eq!(position.to_fen(), fen);
+                assert_eq!(limits.movetime, Some(Duration::from_millis(250)));
+                assert_eq!(limits.depth, None);
+            }
+            _ => panic!("not a search"),
+        }
+        match command(&format!("divide 3 --fen {}", fen)) {
+            Command::Perft {
+                depth,
+                position,
+                divide,
+            } => {
+                assert_eq!((depth, divide), (3, true));
+                assert_eq!(position.to_fen(), fen);
+            }
+            _ => panic!("not a perft"),
+        }
+        match command("eval") {
+            Command::Eval(position) => assert_eq!(position, Position::default()),
+            _ => panic!("not an eval"),
+        }
+    }
+
+    #[test]
+    fn limits() {
+        match command("analyze games.pgn") {
+            Command::Analyze {
+                path,
+                limits,
+                output,
+            } => {
+                assert_eq!(path, "games.pgn");
+                assert_eq!(limits.depth, Some(DEFAULT_DEPTH));
+                assert_eq!(output, None);
+            }
+            _ => panic!("not an analyze"),
+        }
+        match command("analyze games.pgn --output out.pgn --movetime 100") {
+            Command::Analyze { output, .. } => assert_eq!(output.as_deref(), Some("out.pgn")),
+            _ => panic!("not an analyze"),
+        }
+        match command("search --nodes 1000") {
+            Command::Search { limits, .. } => {
+                assert_eq!(limits.nodes, Some(1000));
+                assert_eq!(limits.depth, None);
+            }
+            _ => panic!("not a search"),
+        }
+        assert!(parse_line("search --depth 4 --nodes 10").is_err());
+        assert!(parse_line("search --depth 0").is_err());
+        assert!(parse_line("search --depth").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_usage() {
+        for line in [
+            "fly",
+            "uci extra",
+            "perft",
+            "perft x",
+            "bench 0",
+            "eval --fen nonsense",
+            "eval --depth 3",
+            "analyze",
+            "tune data.txt 0",
+            "datagen 10",
+            "datagen 10 out.txt --format csv",
+        ] {
+            assert!(parse_line(line).is_err(), "{}", line);
+        }
+        match command("datagen 10 out.bin --threads 0 --format binary") {
+            Command::Datagen { options, output } => {
+                assert_eq!((options.games, options.threads), (10, 1));
+                assert!(options.binary);
+                assert_eq!(output, "out.bin");
+            }
+            _ => panic!("not a datagen"),
+        }
+    }
+}
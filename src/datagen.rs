@@ -0,0 +1,370 @@
+use chess::{
+    BitBoard, Board, BoardStatus, ChessMove, Color, MoveGen, Piece, ALL_PIECES, ALL_SQUARES,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use crate::engine::Searcher;
+use crate::nnue::Network;
+use crate::position::Position;
+use crate::weights::EvalParams;
+
+// Training data from self-play. Every game starts with a few random moves and is then played
+// out by fixed-node searches, so a game depends only on its seed and never on timing or on how
+// many threads run. Quiet positions are written with the search score and the game result,
+// both from white's point of view.
+//
+// Text format, one position per line:  <fen> | <score> | <result 1.0, 0.5 or 0.0>
+// Binary format, 33 bytes per position, little-endian:
+//   occupancy u64, piece codes as nibbles in square order (16 bytes, 0-5 white pawn to king,
+//   6-11 black), side to move u8, castling u8 (KQkq in bits 0-3), en passant target u8
+//   (64 for none), halfmove clock u8, fullmove number u16, score i16, result u8 (0, 1, 2)
+
+pub(crate) const RECORD_SIZE: usize = 33;
+
+// positions past this many plies are adjudicated as draws
+const MAX_PLIES: usize = 400;
+
+pub struct DatagenOptions {
+    pub games: usize,
+    pub threads: usize,
+    // per move; the iteration that passes it still finishes, so searches go somewhat over
+    pub nodes: u64,
+    pub seed: u64,
+    pub random_plies: usize,
+    pub binary: bool,
+}
+
+// splitmix64, enough for picking opening moves
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+pub(crate) struct Sample {
+    pub(crate) position: Position,
+    pub(crate) score: i16,
+    pub(crate) result: f64,
+}
+
+fn is_quiet(board: &Board, m: ChessMove) -> bool {
+    *board.checkers() == BitBoard(0)
+        && board.piece_on(m.get_dest()).is_none()
+        && m.get_promotion().is_none()
+        && !(board.piece_on(m.get_source()) == Some(Piece::Pawn)
+            && m.get_source().get_file() != m.get_dest().get_file())
+}
+
+fn insufficient_material(board: &Board) -> bool {
+    let heavy =
+        *board.pieces(Piece::Pawn) | *board.pieces(Piece::Rook) | *board.pieces(Piece::Queen);
+    heavy == BitBoard(0) && board.combined().popcnt() <= 3
+}
+
+// a position after `random_plies` random moves that still has a game left in it
+fn random_opening(rng: &mut Rng, random_plies: usize) -> Position {
+    'retry: loop {
+        let mut position = Position::default();
+        for _ in 0..random_plies {
+            let moves: Vec<ChessMove> = MoveGen::new_legal(&position.board).collect();
+            if moves.is_empty() {
+                continue 'retry;
+            }
+            position = position.make_move_new(moves[rng.below(moves.len())]);
+        }
+        if position.board.status() == BoardStatus::Ongoing {
+            return position;
+        }
+    }
+}
+
+fn play_game(
+    seed: u64,
+    options: &DatagenOptions,
+    params: &Arc<EvalParams>,
+    network: &Option<Arc<Network>>,
+) -> Vec<Sample> {
+    let mut rng = Rng(seed);
+    let mut position = random_opening(&mut rng, options.random_plies);
+    let mut seen: HashMap<u64, usize> = HashMap::new();
+    let mut samples = Vec::new();
+    let mut plies = 0;
+    let result = loop {
+        match position.board.status() {
+            BoardStatus::Checkmate => {
+                break if position.board.side_to_move() == Color::White {
+                    0.0
+                } else {
+                    1.0
+                };
+            }
+            BoardStatus::Stalemate => break 0.5,
+            BoardStatus::Ongoing => {}
+        }
+        let repetitions = seen.entry(position.board.get_hash()).or_insert(0);
+        *repetitions += 1;
+        if *repetitions >= 3
+            || position.halfmove_clock >= 100
+            || insufficient_material(&position.board)
+            || plies >= MAX_PLIES
+        {
+            break 0.5;
+        }
+
+        let mut searcher = Searcher::from_position(&position, 1);
+        searcher.params = params.clone();
+        searcher.network = network.clone();
+        let score = searcher.alpha_beta_with_nodes(options.nodes);
+        let m = searcher.best_move;
+        if !score.is_mate() && is_quiet(&position.board, m) {
+            // the search counts from the side to move, the data from white
+            let white = score.for_side(position.board.side_to_move()).centipawns();
+            samples.push(Sample {
+                position,
+                score: white.clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+                result: 0.0,
+            });
+        }
+        position = position.make_move_new(m);
+        plies += 1;
+    };
+    for sample in &mut samples {
+        sample.result = result;
+    }
+    samples
+}
+
+fn write_text(out: &mut impl Write, sample: &Sample) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "{} | {} | {:.1}",
+        sample.position, sample.score, sample.result
+    )
+}
+
+pub(crate) fn encode(sample: &Sample) -> [u8; RECORD_SIZE] {
+    let board = &sample.position.board;
+    let mut record = [0u8; RECORD_SIZE];
+    record[..8].copy_from_slice(&board.combined().0.to_le_bytes());
+    for (i, square) in board.combined().enumerate() {
+        let piece = board.piece_on(square).unwrap().to_index();
+        let color = board.color_on(square).unwrap().to_index();
+        let code = (piece + 6 * color) as u8;
+        record[8 + i / 2] |= code << (4 * (i % 2));
+    }
+    record[24] = board.side_to_move().to_index() as u8;
+    for (bit, color) in [Color::White, Color::Black].into_iter().enumerate() {
+        let rights = board.castle_rights(color);
+        record[25] |= (rights.has_kingside() as u8) << (2 * bit);
+        record[25] |= (rights.has_queenside() as u8) << (2 * bit + 1);
+    }
+    // the chess crate stores the pawn that can be taken; the FEN wants the square behind it
+    record[26] = match board.en_passant() {
+        Some(pawn) => pawn.uforward(board.side_to_move()).to_index() as u8,
+        None => 64,
+    };
+    record[27] = sample.position.halfmove_clock.min(255) as u8;
+    record[28..30]
+        .copy_from_slice(&(sample.position.fullmove_number.min(65535) as u16).to_le_bytes());
+    record[30..32].copy_from_slice(&sample.score.to_le_bytes());
+    record[32] = (sample.result * 2.0).round() as u8;
+    record
+}
+
+pub(crate) fn decode(record: &[u8]) -> Result<Sample, String> {
+    if record.len() != RECORD_SIZE {
+        return Err(format!("record of {} bytes", record.len()));
+    }
+    let occupied = BitBoard(u64::from_le_bytes(record[..8].try_into().unwrap()));
+    if occupied.popcnt() > 32 {
+        return Err(String::from("more than 32 pieces"));
+    }
+    let mut squares = [None; 64];
+    for (i, square) in occupied.enumerate() {
+        let code = ((record[8 + i / 2] >> (4 * (i % 2))) & 15) as usize;
+        if code >= 12 {
+            return Err(format!("invalid piece code {}", code));
+        }
+        squares[square.to_index()] = Some((ALL_PIECES[code % 6], code >= 6));
+    }
+    let mut placement = String::new();
+    for rank in (0..8).rev() {
+        let mut empty = 0;
+        for file in 0..8 {
+            match squares[rank * 8 + file] {
+                None => empty += 1,
+                Some((piece, black)) => {
+                    if empty > 0 {
+                        placement.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    let c = piece.to_string(Color::White);
+                    placement.push_str(&if black { c.to_lowercase() } else { c });
+                }
+            }
+        }
+        if empty > 0 {
+            placement.push_str(&empty.to_string());
+        }
+        if rank > 0 {
+            placement.push('/');
+        }
+    }
+    let side = if record[24] == 0 { "w" } else { "b" };
+    let castling: String = "KQkq"
+        .chars()
+        .enumerate()
+        .filter(|(bit, _)| record[25] & (1 << bit) != 0)
+        .map(|(_, c)| c)
+        .collect();
+    let castling = if castling.is_empty() {
+        String::from("-")
+    } else {
+        castling
+    };
+    let en_passant = match record[26] {
+        64 => String::from("-"),
+        i if i < 64 => ALL_SQUARES[i as usize].to_string(),
+        i => return Err(format!("invalid en passant square {}", i)),
+    };
+    let fullmove = u16::from_le_bytes([record[28], record[29]]);
+    let fen = format!(
+        "{} {} {} {} {} {}",
+        placement, side, castling, en_passant, record[27], fullmove
+    );
+    Ok(Sample {
+        position: Position::from_fen(&fen)?,
+        score: i16::from_le_bytes([record[30], record[31]]),
+        result: match record[32] {
+            0 => 0.0,
+            1 => 0.5,
+            2 => 1.0,
+            r => return Err(format!("invalid result {}", r)),
+        },
+    })
+}
+
+// plays the games on `options.threads` threads and writes the positions in game order
+pub fn datagen(
+    options: DatagenOptions,
+    output: &str,
+    params: &EvalParams,
+    network: Option<Arc<Network>>,
+) -> Result<(), String> {
+    let file = File::create(output).map_err(|e| format!("cannot create {}: {}", output, e))?;
+    let mut out = BufWriter::new(file);
+    let options = Arc::new(options);
+    let params = Arc::new(params.clone());
+    let (sender, receiver) = mpsc::channel();
+    let mut workers = Vec::new();
+    for thread_index in 0..options.threads.max(1) {
+        let sender = sender.clone();
+        let options = options.clone();
+        let params = params.clone();
+        let network = network.clone();
+        workers.push(thread::spawn(move || {
+            let mut game = thread_index;
+            while game < options.games {
+                let seed = options.seed.wrapping_add(game as u64);
+                let samples = play_game(seed, &options, &params, &network);
+                if sender.send((game, samples)).is_err() {
+                    return;
+                }
+                game += options.threads.max(1);
+            }
+        }));
+    }
+    drop(sender);
+
+    // games finish out of order; hold them back until all earlier ones have been written
+    let mut pending = BTreeMap::new();
+    let mut next = 0;
+    let mut positions = 0;
+    for (game, samples) in receiver {
+        pending.insert(game, samples);
+        while let Some(samples) = pending.remove(&next) {
+            for sample in &samples {
+                if options.binary {
+                    out.write_all(&encode(sample))
+                } else {
+                    write_text(&mut out, sample)
+                }
+                .map_err(|e| format!("cannot write {}: {}", output, e))?;
+            }
+            positions += samples.len();
+            next += 1;
+            if next % 10 == 0 || next == options.games {
+                println!("games {} positions {}", next, positions);
+            }
+        }
+    }
+    for worker in workers {
+        worker.join().map_err(|_| "a datagen thread panicked")?;
+    }
+    out.flush()
+        .map_err(|e| format!("cannot write {}: {}", output, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> DatagenOptions {
+        DatagenOptions {
+            games: 1,
+            threads: 1,
+            nodes: 200,
+            seed: 7,
+            random_plies: 8,
+            binary: false,
+        }
+    }
+
+    #[test]
+    fn binary_records_round_trip() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 37 64",
+        ] {
+            let sample = Sample {
+                position: Position::from_fen(fen).unwrap(),
+                score: -123,
+                result: 0.5,
+            };
+            let decoded = decode(&encode(&sample)).unwrap();
+            assert_eq!(decoded.position, sample.position);
+            assert_eq!(decoded.position.to_fen(), fen);
+            assert_eq!(decoded.score, -123);
+            assert_eq!(decoded.result, 0.5);
+        }
+    }
+
+    #[test]
+    fn games_depend_only_on_the_seed() {
+        let params = Arc::new(EvalParams::default());
+        let first = play_game(7, &options(), &params, &None);
+        let second = play_game(7, &options(), &params, &None);
+        assert!(!first.is_empty());
+        assert_eq!(first.len(), second.len());
+        for (a, b) in first.iter().zip(&second) {
+            assert_eq!(a.position, b.position);
+            assert_eq!(a.score, b.score);
+            assert!(*a.position.board.checkers() == BitBoard(0));
+        }
+    }
+}
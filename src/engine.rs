@@ -1,38 +1,19 @@
 use chess::{BitBoard, Board, BoardStatus, ChessMove, Color, MoveGen, Piece, Square};
-use std;
-use std::cmp::Ordering;
-use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
-use std::sync::mpsc;
-
-use crate::weights::*;
-
-const ALPHA: i32 = i32::MIN;
-const BETA: i32 = i32::MAX;
-
-const PAWN_MG_VAL: i32 = 100;
-const KNIGHT_MG_VAL: i32 = 320;
-const BISHOP_MG_VAL: i32 = 330;
-const ROOK_MG_VAL: i32 = 500;
-const QUEEN_MG_VAL: i32 = 900;
-const KING_MG_VAL: i32 = 20000;
-
-const BISHOP_PAIR_MG_VAL: i32 = 50;
-const ROOK_ON_OPEN_FILE_MG_VAL: i32 = 10;
-const ROOK_ON_SEMI_OPEN_FILE_MG_VAL: i32 = 5;
-const ROOK_ON_7TH_MG_VAL: i32 = 20;
-const ROOK_ON_8TH_MG_VAL: i32 = 30;
-const QUEEN_ON_7TH_MG_VAL: i32 = 10;
-const QUEEN_ON_8TH_MG_VAL: i32 = 20;
-const PAWN_ON_7TH_MG_VAL: i32 = 10;
-const ISOLATED_PAWN_MG_VAL: i32 = -10;
-const DOUBLED_PAWN_MG_VAL: i32 = -10;
-const BACKWARD_PAWN_MG_VAL: i32 = -5;
-const PASSED_PAWN_MG_VAL: i32 = 10;
-const CENTER_CONTROL_MG_VAL: i32 = 5;
-const KING_SAFETY_MG_VAL: i32 = 5;
-const CASTLING_BOTH_MG_VAL: i32 = 20;
-const CASTLING_ONE_MG_VAL: i32 = 10;
+
+use crate::api::{IterationInfo, SearchObserver};
+use crate::attacks::AttackMaps;
+use crate::king::king_safety;
+use crate::minors::minor_pieces;
+use crate::mobility::mobility;
+use crate::nnue::{Accumulator, Network};
+use crate::pawns::pawn_structure;
+use crate::position::{next_halfmove_clock, Position};
+use crate::score::Score;
+use crate::threats::threats;
+use crate::weights::{EvalParams, Term, DEFAULT_PARAMS};
 
 const FILES: [BitBoard; 8] = [
     BitBoard(0x0101010101010101),
@@ -54,491 +35,518 @@ const RANKS: [BitBoard; 8] = [
     BitBoard(0x00FF000000000000),
     BitBoard(0xFF00000000000000),
 ];
-const CENTER: BitBoard = BitBoard(0x0000001818000000);
-
-#[derive(Clone, Copy, Debug, Default)]
-pub struct Score {
-    pub(crate) eval: i32,
-    mate: bool,
-    ply: i32,
-    color: bool, // should only be used when mate is true; true if white is winning
-}
 
 pub(crate) struct Searcher {
     board: Board,
+    halfmove_clock: u32,
+    // distance from the root, which mate scores count from
+    ply: i32,
     pub(crate) depth: u8,
+    pub(crate) nodes: u64,
+    pub(crate) params: Arc<EvalParams>,
+    // evaluates with the network instead of the weights when set
+    pub(crate) network: Option<Arc<Network>>,
+    accumulator: Option<Accumulator>,
     pub(crate) best_move: ChessMove,
-    best_score: Score,
-    children: Vec<Searcher>,
+    pub(crate) best_score: Score,
+    // the best move and the line the child searched after it; children are dropped once
+    // searched, so only their lines are kept
+    line: Vec<ChessMove>,
+    // deepest ply below this node in the last search
+    pub(crate) seldepth: u8,
+    // ends the search when set; the interrupted iteration is thrown away
+    pub(crate) stop: Option<Arc<AtomicBool>>,
+    // whether the last iterate was ended by `stop`
+    pub(crate) stopped: bool,
 }
 
 impl Searcher {
     pub(crate) fn new(board: &Board, depth: u8) -> Searcher {
         Searcher {
-            board: board.clone(),
-            depth: depth,
+            board: *board,
+            halfmove_clock: 0,
+            ply: 0,
+            depth,
+            nodes: 0,
+            params: Arc::new(DEFAULT_PARAMS),
+            network: None,
+            accumulator: None,
             best_move: Default::default(),
-            best_score: Score {
-                eval: 0,
-                mate: false,
-                ply: 0,
-                color: false,
-            },
-            children: Vec::new(),
+            best_score: Score::DRAW,
+            line: Vec::new(),
+            seldepth: 0,
+            stop: None,
+            stopped: false,
         }
     }
-    pub(crate) fn alpha_beta(&mut self, alpha: i32, beta: i32) -> Score {
-        if self.depth == 0 || self.board.status() != BoardStatus::Ongoing {
-            return evaluation_middlegame(&self.board);
-        }
-        let mut alpha = Score {
-            eval: alpha,
-            mate: false,
-            ply: 0,
-            color: false,
-        };
-        let mut beta = Score {
-            eval: beta,
-            mate: false,
-            ply: 0,
-            color: false,
-        };
-        let mut best_score = if self.board.side_to_move() == Color::White {
-            Score {
-                eval: ALPHA,
-                mate: false,
-                ply: 0,
-                color: false,
-            }
-        } else {
-            Score {
-                eval: BETA,
-                mate: false,
-                ply: 0,
-                color: false,
+    pub(crate) fn from_position(position: &Position, depth: u8) -> Searcher {
+        let mut searcher = Searcher::new(&position.board, depth);
+        searcher.halfmove_clock = position.halfmove_clock;
+        searcher
+    }
+    fn child(&self, m: ChessMove) -> Searcher {
+        let board = self.board.make_move_new(m);
+        let accumulator = match (&self.network, &self.accumulator) {
+            (Some(network), Some(accumulator)) => {
+                Some(accumulator.update(network, &self.board, &board))
             }
+            _ => None,
         };
+        Searcher {
+            board,
+            halfmove_clock: next_halfmove_clock(&self.board, m, self.halfmove_clock),
+            ply: self.ply + 1,
+            depth: self.depth - 1,
+            nodes: 0,
+            params: self.params.clone(),
+            network: self.network.clone(),
+            accumulator,
+            best_move: Default::default(),
+            best_score: Score::DRAW,
+            line: Vec::new(),
+            seldepth: 0,
+            stop: self.stop.clone(),
+            stopped: false,
+        }
+    }
+    // negamax: scores are from the side to move's point of view
+    pub(crate) fn alpha_beta(&mut self, alpha: Score, beta: Score) -> Score {
+        self.alpha_beta_reporting(alpha, beta, &mut |_, _| {})
+    }
+    // alpha_beta that calls `current_move` with each move and its number before searching it,
+    // which the root uses to report progress
+    fn alpha_beta_reporting(
+        &mut self,
+        mut alpha: Score,
+        mut beta: Score,
+        current_move: &mut dyn FnMut(ChessMove, usize),
+    ) -> Score {
+        self.nodes += 1;
+        if let (Some(network), None) = (&self.network, &self.accumulator) {
+            self.accumulator = Some(Accumulator::new(network, &self.board));
+        }
+        if self.depth == 0
+            || self.board.status() != BoardStatus::Ongoing
+            || self.halfmove_clock >= 100
+        {
+            return self.evaluate();
+        }
+        // mate distance pruning: nothing below can mate sooner than the next ply or be mated
+        // sooner than now, so a window outside those bounds is already decided. The root keeps
+        // its full window so it always ends up with a move.
+        if self.ply > 0 {
+            alpha = alpha.max(Score::mated_in_plies(self.ply));
+            beta = beta.min(Score::mate_in_plies(self.ply + 1));
+            if alpha >= beta {
+                return alpha;
+            }
+        }
+        let mut best_score = -Score::INFINITE;
         let mut best_move = Default::default();
-        let mut children = Vec::new();
-        if self.board.side_to_move() == Color::White {
-            for m in MoveGen::new_legal(&self.board) {
-                let mut child = Searcher::new(&self.board.make_move_new(m), self.depth - 1);
-                let score = child.alpha_beta(alpha.eval, beta.eval);
-                children.push(child);
-                if score > best_score {
-                    best_score = score;
-                    best_move = m;
-                }
-                if score > alpha {
-                    assert_eq!(score, best_score);
-                    alpha = score;
-                }
-                if alpha >= beta {
-                    break;
-                }
+        let mut line = Vec::new();
+        let mut seldepth = 0;
+        for (i, m) in MoveGen::new_legal(&self.board).enumerate() {
+            current_move(m, i + 1);
+            let mut child = self.child(m);
+            let score = -child.alpha_beta(-beta, -alpha);
+            self.nodes += child.nodes;
+            if self.is_stopped() {
+                return best_score;
             }
-        } else {
-            for m in MoveGen::new_legal(&self.board) {
-                let mut child = Searcher::new(&self.board.make_move_new(m), self.depth - 1);
-                let score = child.alpha_beta(alpha.eval, beta.eval);
-                children.push(child);
-                if score < best_score {
-                    best_score = score;
-                    best_move = m;
-                }
-                if score < beta {
-                    beta = score;
-                }
-                if alpha >= beta {
-                    best_score = score;
-                    break;
-                }
+            seldepth = seldepth.max(child.seldepth);
+            if score > best_score {
+                best_score = score;
+                best_move = m;
+                line = child.line;
+                line.insert(0, m);
+            }
+            if score > alpha {
+                alpha = score;
+            }
+            if alpha >= beta {
+                break;
             }
         }
         self.best_move = best_move;
         self.best_score = best_score;
-        self.children = children;
-        if best_score.mate {
-            if best_score.color {
-                best_score.ply += 1;
-            } else {
-                best_score.ply -= 1;
-            }
-        }
+        self.seldepth = 1 + seldepth;
+        self.line = line;
         best_score
     }
-    pub(crate) fn alpha_beta_with_time(&mut self, time: Duration) -> Score {
-        let start = Instant::now();
-        let mut depth = 1;
-        let mut best_score = Score {
-            eval: 0,
-            mate: false,
-            ply: 0,
-            color: false,
-        };
-        while start.elapsed() < time {
-            let estimated_time = time.as_secs_f64() / 2.5_f64.powi(depth as i32);
-            if estimated_time < 0.1 {
-                break;
+    fn is_stopped(&self) -> bool {
+        self.stop
+            .as_ref()
+            .is_some_and(|stop| stop.load(AtomicOrdering::Relaxed))
+    }
+    // the best line found by the last search
+    pub(crate) fn pv(&self) -> Vec<ChessMove> {
+        self.line.clone()
+    }
+    // from the side to move's point of view, with mates counted from the root
+    fn evaluate(&self) -> Score {
+        let side = self.board.side_to_move();
+        match (&self.network, &self.accumulator) {
+            _ if self.board.status() == BoardStatus::Checkmate => Score::mated_in_plies(self.ply),
+            (Some(network), Some(accumulator)) if self.board.status() == BoardStatus::Ongoing => {
+                let eval = Score::cp(network.evaluate(accumulator, side));
+                with_clock(eval, self.halfmove_clock)
+            }
+            _ => {
+                evaluation_with_clock(&self.board, &self.params, self.halfmove_clock).for_side(side)
             }
-            best_score = self.alpha_beta(ALPHA, BETA);
-            self.depth += 1;
         }
-        best_score
     }
-    pub(crate) fn alpha_beta_until_stopped(&mut self, reciever: mpsc::Receiver<bool>) -> Score {
+    // iterative deepening from depth 1, reporting every iteration, until `deeper` says no or the
+    // search is stopped. The first iteration always completes, so there is a move to play.
+    pub(crate) fn iterate(
+        &mut self,
+        observer: &mut dyn SearchObserver,
+        mut deeper: impl FnMut(&Searcher, Duration) -> bool,
+    ) -> Score {
+        let start = Instant::now();
+        let stop = self.stop.take();
+        self.depth = 0;
+        self.stopped = false;
+        let mut best_score = self.best_score;
         loop {
-            if reciever.try_recv() == Ok(true) {
-                break;
-            }
-            self.alpha_beta(ALPHA, BETA);
             self.depth += 1;
+            let depth = self.depth;
+            let score =
+                self.alpha_beta_reporting(-Score::INFINITE, Score::INFINITE, &mut |m, number| {
+                    observer.current_move(m, number, depth)
+                });
+            if self.is_stopped() {
+                self.depth -= 1;
+                self.stopped = true;
+                return best_score;
+            }
+            best_score = score;
+            let seldepth = self.seldepth;
+            observer.iteration(&IterationInfo {
+                depth,
+                seldepth,
+                score,
+                pv: self.pv(),
+                nodes: self.nodes,
+                elapsed: start.elapsed(),
+            });
+            if depth == 1 {
+                self.stop = stop.clone();
+            }
+            if self.depth == u8::MAX || !deeper(self, start.elapsed()) {
+                self.stop = stop;
+                return score;
+            }
         }
-        return self.best_score;
+    }
+    // deepens until an iteration ends with at least `nodes` searched in total, so the result
+    // does not depend on how fast the machine is
+    pub(crate) fn alpha_beta_with_nodes(&mut self, nodes: u64) -> Score {
+        self.iterate(&mut (), |searcher, _| {
+            searcher.nodes < nodes && !searcher.best_score.is_mate()
+        })
     }
 }
 
-impl Score {
-    fn new_eval(eval: i32) -> Score {
-        Score {
-            eval: eval,
-            mate: false,
-            ply: 0,
-            color: false,
-        }
-    }
-    fn new_mate(ply: i32, color: bool) -> Score {
-        Score {
-            eval: 0,
-            mate: true,
-            ply: ply,
-            color: color,
-        }
-    }
+// an iteration takes a few times longer than the one before it, so another one is only started
+// while it can be expected to finish in time
+pub(crate) fn time_for_another_iteration(elapsed: Duration, time: Duration) -> bool {
+    elapsed.as_secs_f64() * 2.5 < time.as_secs_f64()
 }
 
-impl PartialEq<Self> for Score {
-    fn eq(&self, other: &Self) -> bool {
-        if self.mate == other.mate {
-            if self.mate {
-                self.ply == other.ply && self.color == other.color // only matters if mate is true
-            } else {
-                self.eval == other.eval // only matters if mate is false
-            }
-        } else {
-            false // mate isn't equal to non-mate
-        }
-    }
+// Every weight the evaluation applies goes through Evaluation::add, which also reports it to a
+// Trace with the side it counts for. The tuner and the eval command are both traces, so they see
+// exactly what the search sees.
+pub(crate) trait Trace {
+    fn add(&mut self, term: Term, color: Color, count: i32);
 }
 
-impl PartialOrd<Self> for Score {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        if self.mate {
-            if other.mate {
-                if self.color == other.color {
-                    return self.ply.partial_cmp(&other.ply);
-                } else if self.color {
-                    return Some(Ordering::Greater);
-                } else {
-                    return Some(Ordering::Less);
-                }
-            } else {
-                return Some(Ordering::Greater);
-            }
-        } else {
-            return self.eval.partial_cmp(&other.eval);
+pub(crate) struct NoTrace;
+
+impl Trace for NoTrace {
+    #[inline(always)]
+    fn add(&mut self, _term: Term, _color: Color, _count: i32) {}
+}
+
+struct Evaluation<'a, T: Trace> {
+    params: &'a EvalParams,
+    trace: &'a mut T,
+    mg: i32,
+    eg: i32,
+}
+
+impl<'a, T: Trace> Evaluation<'a, T> {
+    fn add(&mut self, term: Term, color: Color, count: i32) {
+        if count == 0 {
+            return;
         }
+        let weight = self.params.weight(term);
+        let signed = if color == Color::White { count } else { -count };
+        self.mg += weight.mg * signed;
+        self.eg += weight.eg * signed;
+        self.trace.add(term, color, count);
     }
 }
 
-pub(crate) fn evaluation_middlegame(board: &Board) -> Score {
-    let mut evaluation = 0;
-    if board.status() == BoardStatus::Ongoing {
-        // Bitboards
-        let pawns = board.pieces(Piece::Pawn);
-        let knights = board.pieces(Piece::Knight);
-        let bishops = board.pieces(Piece::Bishop);
-        let rooks = board.pieces(Piece::Rook);
-        let queens = board.pieces(Piece::Queen);
-        let kings = board.pieces(Piece::King);
-        let white = board.color_combined(Color::White);
-        let black = board.color_combined(Color::Black);
-        let white_pawns = pawns & white;
-        let black_pawns = pawns & black;
-        let white_knights = knights & white;
-        let black_knights = knights & black;
-        let white_bishops = bishops & white;
-        let black_bishops = bishops & black;
-        let white_rooks = rooks & white;
-        let black_rooks = rooks & black;
-        let white_queens = queens & white;
-        let black_queens = queens & black;
-        let white_kings = kings & white;
-        let black_kings = kings & black;
-
-        // Piece counts
-        let white_pawn_count = white_pawns.popcnt();
-        let black_pawn_count = black_pawns.popcnt();
-        let white_knight_count = white_knights.popcnt();
-        let black_knight_count = black_knights.popcnt();
-        let white_bishop_count = white_bishops.popcnt();
-        let black_bishop_count = black_bishops.popcnt();
-        let white_rook_count = white_rooks.popcnt();
-        let black_rook_count = black_rooks.popcnt();
-        let white_queen_count = white_queens.popcnt();
-        let black_queen_count = black_queens.popcnt();
-        let white_king_count = white_kings.popcnt();
-        let black_king_count = black_kings.popcnt();
-
-        // Total pieces bitboards
-        let all_pieces = board.combined();
-        let white_pieces = board.color_combined(Color::White);
-        let black_pieces = board.color_combined(Color::Black);
-
-        // Total piece counts
-        let all_pieces_count = all_pieces.popcnt();
-        let white_pieces_count = white_pieces.popcnt();
-        let black_pieces_count = black_pieces.popcnt();
-
-        // Material
-        evaluation += PAWN_MG_VAL * (white_pawn_count as i32 - black_pawn_count as i32);
-        evaluation += KNIGHT_MG_VAL * (white_knight_count as i32 - black_knight_count as i32);
-        evaluation += BISHOP_MG_VAL * (white_bishop_count as i32 - black_bishop_count as i32);
-        evaluation += ROOK_MG_VAL * (white_rook_count as i32 - black_rook_count as i32);
-        evaluation += QUEEN_MG_VAL * (white_queen_count as i32 - black_queen_count as i32);
-        evaluation += KING_MG_VAL * (white_king_count as i32 - black_king_count as i32);
-
-        // Bishop pairs
-        if black_bishop_count == 2 {
-            evaluation += BISHOP_PAIR_MG_VAL;
-        }
+pub(crate) const MAX_PHASE: i32 = 24;
 
-        if white_bishop_count == 2 {
-            evaluation -= BISHOP_PAIR_MG_VAL;
-        }
+// 24 with all minor and major pieces on the board, 0 with only kings and pawns
+pub(crate) fn game_phase(board: &Board) -> i32 {
+    let phase = board.pieces(Piece::Knight).popcnt()
+        + board.pieces(Piece::Bishop).popcnt()
+        + 2 * board.pieces(Piece::Rook).popcnt()
+        + 4 * board.pieces(Piece::Queen).popcnt();
+    (phase as i32).min(MAX_PHASE)
+}
 
-        // Rook logic
-        if white_rook_count > 0 {
-            // Rook on open file
-            let white_rooks_files = [
-                white_rooks & FILES[0],
-                white_rooks & FILES[1],
-                white_rooks & FILES[2],
-                white_rooks & FILES[3],
-                white_rooks & FILES[4],
-                white_rooks & FILES[5],
-                white_rooks & FILES[6],
-                white_rooks & FILES[7],
-            ];
-            for fileindex in 0..7 {
-                let file = white_rooks_files[fileindex];
-                if file.popcnt() > 0 {
-                    if FILES[fileindex] & white_pawns == BitBoard::new(0)
-                        && FILES[fileindex] & black_pawns == BitBoard::new(0)
-                    {
-                        evaluation += ROOK_ON_OPEN_FILE_MG_VAL;
-                    }
-                    // Rook on half open file
-                    else if FILES[fileindex] & white_pawns == BitBoard::new(0) {
-                        evaluation += ROOK_ON_SEMI_OPEN_FILE_MG_VAL;
-                    }
-                }
-            }
-            // rook on 7th rank
-            if white_rooks & RANKS[6] != BitBoard::new(0) {
-                evaluation += ROOK_ON_7TH_MG_VAL;
-            }
-            // rook on 8th rank
-            if white_rooks & RANKS[7] != BitBoard::new(0) {
-                evaluation += ROOK_ON_8TH_MG_VAL;
-            }
-            // castling rights
-            if board.castle_rights(Color::White) == chess::CastleRights::Both {
-                evaluation += CASTLING_BOTH_MG_VAL;
-            } else if board.castle_rights(Color::White) != chess::CastleRights::NoRights{
-                evaluation += CASTLING_ONE_MG_VAL;
-            }
-        }
+// middlegame and endgame blended by the material left on the board
+pub(crate) fn taper(mg: i32, eg: i32, phase: i32) -> i32 {
+    (mg * phase + eg * (MAX_PHASE - phase)) / MAX_PHASE
+}
 
-        if black_rook_count > 0 {
-            let black_rooks_files = [
-                black_rooks & FILES[0],
-                black_rooks & FILES[1],
-                black_rooks & FILES[2],
-                black_rooks & FILES[3],
-                black_rooks & FILES[4],
-                black_rooks & FILES[5],
-                black_rooks & FILES[6],
-                black_rooks & FILES[7],
-            ];
-            for fileindex in 0..7 {
-                let file = black_rooks_files[fileindex];
-                if file.popcnt() > 0 {
-                    if FILES[fileindex] & black_pawns == BitBoard::new(0)
-                        && FILES[fileindex] & white_pawns == BitBoard::new(0)
-                    {
-                        evaluation -= ROOK_ON_OPEN_FILE_MG_VAL;
-                    } else if FILES[fileindex] & black_pawns == BitBoard::new(0) {
-                        evaluation -= ROOK_ON_SEMI_OPEN_FILE_MG_VAL;
-                    }
-                }
-            }
-            // rook on 7th rank
-            if black_rooks & RANKS[1] != BitBoard::new(0) {
-                evaluation -= ROOK_ON_7TH_MG_VAL;
-            }
-            // rook on 8th rank
-            if black_rooks & RANKS[0] != BitBoard::new(0) {
-                evaluation -= ROOK_ON_8TH_MG_VAL;
-            }
-            // castling rights
-            if board.castle_rights(Color::Black) == chess::CastleRights::Both {
-                evaluation -= CASTLING_BOTH_MG_VAL;
-            } else if board.castle_rights(Color::Black) != chess::CastleRights::NoRights{
-                evaluation -= CASTLING_ONE_MG_VAL;
+// index into a table laid out with the eighth rank first, mirrored for black
+pub(crate) fn table_index(square: Square, color: Color) -> usize {
+    let rank = square.get_rank().to_index();
+    let row = if color == Color::White { 7 - rank } else { rank };
+    row * 8 + square.get_file().to_index()
+}
+
+pub(crate) fn evaluate<T: Trace>(board: &Board, params: &EvalParams, trace: &mut T) -> Score {
+    match board.status() {
+        BoardStatus::Ongoing => {}
+        BoardStatus::Checkmate => return Score::mated_in_plies(0).for_side(board.side_to_move()),
+        BoardStatus::Stalemate => return Score::DRAW,
+    }
+    let mut e = Evaluation {
+        params,
+        trace,
+        mg: 0,
+        eg: 0,
+    };
+
+    // Bitboards
+    let pawns = board.pieces(Piece::Pawn);
+    let rooks = board.pieces(Piece::Rook);
+    let white = board.color_combined(Color::White);
+    let black = board.color_combined(Color::Black);
+    let white_pawns = pawns & white;
+    let black_pawns = pawns & black;
+    let white_rooks = rooks & white;
+    let black_rooks = rooks & black;
+
+    // Material and piece-square tables
+    for piece in [
+        Piece::Pawn,
+        Piece::Knight,
+        Piece::Bishop,
+        Piece::Rook,
+        Piece::Queen,
+    ] {
+        e.add(
+            Term::Material(piece),
+            Color::White,
+            (board.pieces(piece) & white).popcnt() as i32,
+        );
+        e.add(
+            Term::Material(piece),
+            Color::Black,
+            (board.pieces(piece) & black).popcnt() as i32,
+        );
+    }
+    for piece in chess::ALL_PIECES {
+        for color in [Color::White, Color::Black] {
+            for square in *board.pieces(piece) & *board.color_combined(color) {
+                e.add(
+                    Term::PieceSquare(piece, table_index(square, color)),
+                    color,
+                    1,
+                );
             }
         }
+    }
 
-        // Pawn logic
-        if white_pawn_count > 0 {
-            // Isolated pawns
-            let white_pawns_files = [
-                white_pawns & FILES[0],
-                white_pawns & FILES[1],
-                white_pawns & FILES[2],
-                white_pawns & FILES[3],
-                white_pawns & FILES[4],
-                white_pawns & FILES[5],
-                white_pawns & FILES[6],
-                white_pawns & FILES[7],
-            ];
-            let white_pawns_ranks = [
-                white_pawns & RANKS[0],
-                white_pawns & RANKS[1],
-                white_pawns & RANKS[2],
-                white_pawns & RANKS[3],
-                white_pawns & RANKS[4],
-                white_pawns & RANKS[5],
-                white_pawns & RANKS[6],
-                white_pawns & RANKS[7],
-            ];
-            for fileindex in 0..7 {
-                let file = white_pawns_files[fileindex];
-                if file.popcnt() > 0 {
-                    if FILES[fileindex] & white_pawns == BitBoard::new(0)
-                        && FILES[fileindex] & black_pawns == BitBoard::new(0)
-                    {
-                        evaluation += ISOLATED_PAWN_MG_VAL;
-                    }
+    // Rook logic
+    if white_rooks.popcnt() > 0 {
+        for file in FILES {
+            if (white_rooks & file).popcnt() > 0 {
+                // Rook on open file
+                if file & white_pawns == BitBoard::new(0) && file & black_pawns == BitBoard::new(0)
+                {
+                    e.add(Term::RookOnOpenFile, Color::White, 1);
                 }
-            }
-            // Doubled pawns
-            for rankindex in 0..7 {
-                let rank = white_pawns_ranks[rankindex];
-                if rank.popcnt() > 1 {
-                    evaluation += DOUBLED_PAWN_MG_VAL;
+                // Rook on half open file
+                else if file & white_pawns == BitBoard::new(0) {
+                    e.add(Term::RookOnSemiOpenFile, Color::White, 1);
                 }
             }
-            // Passed pawns
-            for rankindex in 0..7 {
-                let rank = white_pawns_ranks[rankindex];
-                if rank.popcnt() > 0 {
-                    if RANKS[rankindex] & black_pawns == BitBoard::new(0) {
-                        evaluation += PASSED_PAWN_MG_VAL;
-                    }
-                }
-            }
-            // Pawn on 7th rank
-            if white_pawns & RANKS[6] != BitBoard::new(0) {
-                evaluation += PAWN_ON_7TH_MG_VAL;
-            }
-            // Central Control
-            evaluation += (white_pawns & CENTER).popcnt() as i32 * CENTER_CONTROL_MG_VAL;
         }
-        if black_pawn_count > 0 {
-            // Isolated pawns
-            let black_pawns_files = [
-                black_pawns & FILES[0],
-                black_pawns & FILES[1],
-                black_pawns & FILES[2],
-                black_pawns & FILES[3],
-                black_pawns & FILES[4],
-                black_pawns & FILES[5],
-                black_pawns & FILES[6],
-                black_pawns & FILES[7],
-            ];
-            let black_pawns_ranks = [
-                black_pawns & RANKS[0],
-                black_pawns & RANKS[1],
-                black_pawns & RANKS[2],
-                black_pawns & RANKS[3],
-                black_pawns & RANKS[4],
-                black_pawns & RANKS[5],
-                black_pawns & RANKS[6],
-                black_pawns & RANKS[7],
-            ];
-            for fileindex in 0..7 {
-                let file = black_pawns_files[fileindex];
-                if file.popcnt() > 0 {
-                    if FILES[fileindex] & black_pawns == BitBoard::new(0)
-                        && FILES[fileindex] & white_pawns == BitBoard::new(0)
-                    {
-                        evaluation -= ISOLATED_PAWN_MG_VAL;
-                    }
-                }
-            }
-            // Doubled pawns
-            for rankindex in 0..7 {
-                let rank = black_pawns_ranks[rankindex];
-                if rank.popcnt() > 1 {
-                    evaluation -= DOUBLED_PAWN_MG_VAL;
-                }
-            }
-            // Passed pawns
-            for rankindex in 0..7 {
-                let rank = black_pawns_ranks[rankindex];
-                if rank.popcnt() > 0 {
-                    if RANKS[rankindex] & white_pawns == BitBoard::new(0) {
-                        evaluation -= PASSED_PAWN_MG_VAL;
-                    }
+        // rook on 7th rank
+        if white_rooks & RANKS[6] != BitBoard::new(0) {
+            e.add(Term::RookOn7th, Color::White, 1);
+        }
+        // rook on 8th rank
+        if white_rooks & RANKS[7] != BitBoard::new(0) {
+            e.add(Term::RookOn8th, Color::White, 1);
+        }
+        // castling rights
+        if board.castle_rights(Color::White) == chess::CastleRights::Both {
+            e.add(Term::CastlingBoth, Color::White, 1);
+        } else if board.castle_rights(Color::White) != chess::CastleRights::NoRights {
+            e.add(Term::CastlingOne, Color::White, 1);
+        }
+    }
+
+    if black_rooks.popcnt() > 0 {
+        for file in FILES {
+            if (black_rooks & file).popcnt() > 0 {
+                if file & black_pawns == BitBoard::new(0) && file & white_pawns == BitBoard::new(0)
+                {
+                    e.add(Term::RookOnOpenFile, Color::Black, 1);
+                } else if file & black_pawns == BitBoard::new(0) {
+                    e.add(Term::RookOnSemiOpenFile, Color::Black, 1);
                 }
             }
-            // Pawn on 7th rank
-            if black_pawns & RANKS[1] != BitBoard::new(0) {
-                evaluation -= PAWN_ON_7TH_MG_VAL;
-            }
-            // Central Control
-            if black_pawns & CENTER != BitBoard::new(0) {
-                evaluation -= CENTER_CONTROL_MG_VAL;
-            }
         }
-        return Score::new_eval(evaluation);
-    } else if board.status() == BoardStatus::Checkmate {
-        if board.side_to_move() == Color::White {
-            return Score::new_mate(-0, false);
-        } else {
-            return Score::new_mate(0, true);
+        // rook on 7th rank
+        if black_rooks & RANKS[1] != BitBoard::new(0) {
+            e.add(Term::RookOn7th, Color::Black, 1);
+        }
+        // rook on 8th rank
+        if black_rooks & RANKS[0] != BitBoard::new(0) {
+            e.add(Term::RookOn8th, Color::Black, 1);
+        }
+        // castling rights
+        if board.castle_rights(Color::Black) == chess::CastleRights::Both {
+            e.add(Term::CastlingBoth, Color::Black, 1);
+        } else if board.castle_rights(Color::Black) != chess::CastleRights::NoRights {
+            e.add(Term::CastlingOne, Color::Black, 1);
         }
-    } else if board.status() == BoardStatus::Stalemate {
-        evaluation = 0;
     }
-    return Score::new_eval(evaluation);
+
+    // Attack maps, then mobility, king safety and threats from them
+    let maps = AttackMaps::new(board);
+    mobility(board, &maps, |term, color, count| e.add(term, color, count));
+    king_safety(board, &maps, |term, color, count| e.add(term, color, count));
+    threats(board, &maps, |term, color, count| e.add(term, color, count));
+    minor_pieces(board, &maps, |term, color, count| e.add(term, color, count));
+
+    // Pawn structure, cached by pawn configuration
+    pawn_structure(board).for_each_term(|term, color, count| e.add(term, color, count));
+
+    Score::cp(taper(e.mg, e.eg, game_phase(board)))
 }
 
-pub fn best_move(board: &Board, depth: u8) -> (ChessMove, i32) {
-    let mut searcher = Searcher::new(board, depth);
-    searcher.alpha_beta(ALPHA, BETA);
-    return (searcher.best_move, searcher.best_score.eval);
+// drifts the evaluation towards a draw as the fifty-move rule approaches
+pub(crate) fn evaluation_with_clock(
+    board: &Board,
+    params: &EvalParams,
+    halfmove_clock: u32,
+) -> Score {
+    with_clock(evaluate(board, params, &mut NoTrace), halfmove_clock)
 }
 
-pub fn best_move_with_time(board: &Board, time: u64) -> (ChessMove, i32) {
-    let mut searcher = Searcher::new(board, 0);
-    searcher.alpha_beta_with_time(Duration::from_millis(time));
-    return (searcher.best_move, searcher.best_score.eval);
+fn with_clock(score: Score, halfmove_clock: u32) -> Score {
+    if score.is_mate() {
+        return score;
+    }
+    if halfmove_clock >= 100 {
+        return Score::DRAW;
+    }
+    Score::cp(score.centipawns() * (200 - halfmove_clock as i32) / 200)
 }
 
-pub fn best_move_infinite_thread(board: &Board, reciever: mpsc::Receiver<bool>) -> (ChessMove, i32) {
-    let mut searcher = Searcher::new(board, 1);
-    searcher.alpha_beta_until_stopped(reciever);
-    return (searcher.best_move.clone(), searcher.best_score.eval.clone());
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nnue::{network_bytes, FeatureSet};
+
+    fn search(fen: &str, depth: u8) -> (Score, Vec<ChessMove>) {
+        let position = Position::from_fen(fen).unwrap();
+        let mut searcher = Searcher::from_position(&position, depth);
+        let score = searcher.alpha_beta(-Score::INFINITE, Score::INFINITE);
+        (score, searcher.pv())
+    }
+
+    #[test]
+    fn searches_with_a_network() {
+        let bytes = network_bytes(FeatureSet::HalfKA, 32);
+        let network = Arc::new(Network::from_bytes(&bytes).unwrap());
+        // castling, captures and king moves update the accumulators every way they can be
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let board = Position::from_fen(fen).unwrap().board;
+        // one ply deep, the search sees the network's evaluation of every move
+        let mut searcher = Searcher::new(&board, 1);
+        searcher.network = Some(network.clone());
+        let score = searcher.alpha_beta(-Score::INFINITE, Score::INFINITE);
+        let expected = MoveGen::new_legal(&board)
+            .map(|m| {
+                let next = board.make_move_new(m);
+                let accumulator = Accumulator::new(&network, &next);
+                let eval = Score::cp(network.evaluate(&accumulator, next.side_to_move()));
+                -with_clock(eval, next_halfmove_clock(&board, m, 0))
+            })
+            .max()
+            .unwrap();
+        assert_eq!(score, expected);
+        let mut searcher = Searcher::new(&board, 3);
+        searcher.network = Some(network);
+        searcher.alpha_beta(-Score::INFINITE, Score::INFINITE);
+        assert!(board.legal(searcher.best_move));
+        assert_eq!(searcher.pv().len(), 3);
+    }
+
+    #[test]
+    fn exact_mate_distances() {
+        // 1. Ra6 bxa6 2. b7#
+        let (score, pv) = search("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1", 3);
+        assert_eq!(score, Score::mate_in_plies(3));
+        assert_eq!(pv[0].to_string(), "a1a6");
+        // 1. Ra6 f6 2. Bxf6+ Rg7 3. Rxa8#
+        let (score, pv) = search("r5rk/5p1p/5R2/4B3/8/8/7P/7K w - - 0 1", 5);
+        assert_eq!(score.mate_in(), Some(3));
+        assert_eq!(pv.len(), 5);
+    }
+
+    #[test]
+    fn delays_being_mated() {
+        // after 1. Ra6 black cannot avoid mate, but can put it off for two more moves
+        let (score, pv) = search("r5rk/5p1p/R7/4B3/8/8/7P/7K b - - 1 1", 4);
+        assert_eq!(score, Score::mated_in_plies(4));
+        assert_eq!(score.mate_in(), Some(-2));
+        assert_eq!(pv.len(), 4);
+    }
+
+    #[test]
+    fn keeps_a_move_when_every_move_loses() {
+        // black is mated whatever it plays; a window no move reaches must not leave it without one
+        let position = Position::from_fen("r5rk/5p1p/R7/4B3/8/8/7P/7K b - - 1 1").unwrap();
+        for (alpha, beta) in [
+            (-Score::INFINITE, Score::INFINITE),
+            (Score::cp(0), Score::cp(1)),
+        ] {
+            let mut searcher = Searcher::from_position(&position, 4);
+            assert!(searcher.alpha_beta(alpha, beta).mate_in() < Some(0));
+            assert!(MoveGen::new_legal(&position.board).any(|m| m == searcher.best_move));
+        }
+    }
+
+    #[test]
+    fn prefers_the_shortest_mate() {
+        // Ra8 mates at once; a deeper search also sees slower mates and must not prefer them
+        for depth in 1..=5 {
+            let (score, pv) = search("6k1/5ppp/8/8/8/8/8/R3R1K1 w - - 0 1", depth);
+            assert_eq!(score, Score::mate_in_plies(1));
+            assert_eq!(pv[0].to_string(), "a1a8");
+        }
+    }
+
+    #[test]
+    fn deeper_search_reports_the_same_mate() {
+        let position = Position::from_fen("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1").unwrap();
+        let mut deeper = Searcher::from_position(&position, 5);
+        assert_eq!(
+            deeper.alpha_beta(-Score::INFINITE, Score::INFINITE),
+            Score::mate_in_plies(3)
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use chess::Board;
+use std::str::FromStr;
+
+// FEN validation. The chess crate silently wraps around on malformed piece placement,
+// so everything is checked here before a string is handed to Board::from_str.
+
+pub(crate) const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+pub(crate) fn parse_fen(fen: &str) -> Result<Board, String> {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    if fields.len() < 4 || fields.len() > 6 {
+        return Err(format!("expected 4 to 6 FEN fields, found {}", fields.len()));
+    }
+    validate_placement(fields[0])?;
+    if fields[1] != "w" && fields[1] != "b" {
+        return Err(format!("invalid side to move '{}'", fields[1]));
+    }
+    validate_castling(fields[2])?;
+    validate_en_passant(fields[3], fields[1])?;
+    if let Some(halfmove) = fields.get(4) {
+        halfmove
+            .parse::<u32>()
+            .map_err(|_| format!("invalid halfmove clock '{}'", halfmove))?;
+    }
+    if let Some(fullmove) = fields.get(5) {
+        match fullmove.parse::<u32>() {
+            Ok(n) if n > 0 => {}
+            _ => return Err(format!("invalid fullmove number '{}'", fullmove)),
+        }
+    }
+    Board::from_str(&fields.join(" "))
+        .map_err(|_| String::from("position is not legal (kings, checks or castling rights)"))
+}
+
+fn validate_placement(placement: &str) -> Result<(), String> {
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(format!("expected 8 ranks, found {}", ranks.len()));
+    }
+    for (i, rank) in ranks.iter().enumerate() {
+        let mut files = 0;
+        for c in rank.chars() {
+            match c {
+                '1'..='8' => files += c as u32 - '0' as u32,
+                'p' | 'n' | 'b' | 'r' | 'q' | 'k' | 'P' | 'N' | 'B' | 'R' | 'Q' | 'K' => files += 1,
+                _ => return Err(format!("invalid character '{}' in piece placement", c)),
+            }
+        }
+        if files != 8 {
+            return Err(format!("rank {} has {} files", 8 - i, files));
+        }
+    }
+    // the chess crate panics on a board without a king
+    for king in ['K', 'k'] {
+        let count = placement.matches(king).count();
+        if count != 1 {
+            return Err(format!("expected one '{}', found {}", king, count));
+        }
+    }
+    Ok(())
+}
+
+fn validate_castling(castling: &str) -> Result<(), String> {
+    if castling == "-" {
+        return Ok(());
+    }
+    let mut seen = String::new();
+    for c in castling.chars() {
+        if !"KQkq".contains(c) || seen.contains(c) {
+            return Err(format!("invalid castling rights '{}'", castling));
+        }
+        seen.push(c);
+    }
+    Ok(())
+}
+
+fn validate_en_passant(square: &str, side: &str) -> Result<(), String> {
+    if square == "-" {
+        return Ok(());
+    }
+    let expected_rank = if side == "w" { '6' } else { '3' };
+    let mut chars = square.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('a'..='h'), Some(rank), None) if rank == expected_rank => Ok(()),
+        _ => Err(format!("invalid en passant square '{}'", square)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_four_to_six_fields() {
+        assert!(parse_fen(START_FEN).is_ok());
+        assert!(parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -").is_ok());
+        assert!(parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0").is_ok());
+    }
+
+    #[test]
+    fn rejects_a_wrong_field_count() {
+        assert_eq!(
+            parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq"),
+            Err(String::from("expected 4 to 6 FEN fields, found 3"))
+        );
+        assert!(parse_fen(&format!("{} 7", START_FEN)).is_err());
+        assert!(parse_fen("").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_fields() {
+        for fen in [
+            // seven ranks, a rank of nine files, an unknown piece
+            "rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkk - 0 1",
+            // an en passant square on the wrong rank for the side to move
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0",
+            // a missing king, two kings of one colour
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQQBNR w kq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBKKBNR w - - 0 1",
+            "rnbkkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+        ] {
+            assert!(parse_fen(fen).is_err(), "{}", fen);
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use chess::{
+    get_adjacent_files, get_file, get_king_moves, BitBoard, Board, Color, File, Piece, Square,
+    EMPTY,
+};
+
+use crate::attacks::{piece_attacks, AttackMaps};
+use crate::pawns::relative_rank;
+use crate::weights::Term;
+
+// King safety. Enemy pieces bearing on the squares around the king add up to attack units,
+// which index a non-linear table: one attacker is a nuisance, three are a mating attack.
+// The pawn cover in front of the king and the files around it are weighed separately.
+
+// the table in the weights file has this many entries
+pub(crate) const MAX_ATTACK_UNITS: usize = 64;
+
+// units for each enemy piece that attacks the king zone, by piece
+const ATTACKER_UNITS: [usize; 6] = [0, 2, 2, 3, 5, 0];
+
+// units for a check the enemy can give without losing the checking piece, by piece
+const SAFE_CHECK_UNITS: [usize; 6] = [0, 3, 2, 4, 3, 0];
+
+// the king's square and its neighbours, plus the three squares beyond them towards the enemy
+fn king_zone(king: Square, color: Color) -> BitBoard {
+    let near = get_king_moves(king) | BitBoard::from_square(king);
+    match king.forward(color) {
+        Some(square) => near | (get_king_moves(square) & get_rank_ahead(king, color, 2)),
+        None => near,
+    }
+}
+
+// the rank `distance` ranks in front of the king, if it exists
+fn get_rank_ahead(king: Square, color: Color, distance: usize) -> BitBoard {
+    let rank = relative_rank(king, color) + distance;
+    if rank > 7 {
+        return EMPTY;
+    }
+    let rank = if color == Color::White {
+        rank
+    } else {
+        7 - rank
+    };
+    chess::get_rank(chess::Rank::from_index(rank))
+}
+
+fn attack_units(board: &Board, maps: &AttackMaps, color: Color, king: Square) -> usize {
+    let enemy = !color;
+    let occupied = *board.combined();
+    let zone = king_zone(king, color);
+    let defended = maps.side(color);
+
+    let mut attackers = 0;
+    let mut units = 0;
+    let mut safe_checks = 0;
+    for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+        // the squares a piece of this kind would give check from
+        let checks =
+            piece_attacks(piece, king, color, occupied) & !board.color_combined(enemy) & !defended;
+        let mut checks_available = false;
+        for square in *board.pieces(piece) & *board.color_combined(enemy) {
+            let attacks = piece_attacks(piece, square, enemy, occupied);
+            if attacks & zone != EMPTY {
+                attackers += 1;
+                units += ATTACKER_UNITS[piece.to_index()] + (attacks & zone).popcnt() as usize;
+            }
+            if attacks & checks != EMPTY {
+                checks_available = true;
+            }
+        }
+        if checks_available {
+            safe_checks += SAFE_CHECK_UNITS[piece.to_index()];
+        }
+    }
+    // a lone attacker cannot break through on its own
+    if attackers < 2 {
+        units = 0;
+    }
+    (units + safe_checks).min(MAX_ATTACK_UNITS - 1)
+}
+
+// hands every king-safety feature of both sides to `add`, counted for the side whose king it
+// concerns
+pub(crate) fn king_safety(board: &Board, maps: &AttackMaps, mut add: impl FnMut(Term, Color, i32)) {
+    let pawns = *board.pieces(Piece::Pawn);
+    for color in [Color::White, Color::Black] {
+        let king = board.king_square(color);
+        let own_pawns = pawns & *board.color_combined(color);
+        let enemy_pawns = pawns & *board.color_combined(!color);
+
+        let units = attack_units(board, maps, color, king);
+        if units > 0 {
+            add(Term::KingDanger(units), color, 1);
+        }
+
+        let files = get_file(king.get_file()) | get_adjacent_files(king.get_file());
+        for distance in 1..=2 {
+            let shield = own_pawns & files & get_rank_ahead(king, color, distance);
+            add(
+                Term::PawnShield(distance - 1),
+                color,
+                shield.popcnt() as i32,
+            );
+        }
+        for distance in 1..=4 {
+            let storm = enemy_pawns & files & get_rank_ahead(king, color, distance);
+            add(Term::PawnStorm(distance - 1), color, storm.popcnt() as i32);
+        }
+
+        let first = king.get_file().to_index().saturating_sub(1);
+        let last = (king.get_file().to_index() + 1).min(7);
+        for file in first..=last {
+            let file = get_file(File::from_index(file));
+            if file & pawns == EMPTY {
+                add(Term::KingOpenFile, color, 1);
+            } else if file & own_pawns == EMPTY {
+                add(Term::KingSemiOpenFile, color, 1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn units(fen: &str, color: Color) -> usize {
+        let board = Board::from_str(fen).unwrap();
+        attack_units(
+            &board,
+            &AttackMaps::new(&board),
+            color,
+            board.king_square(color),
+        )
+    }
+
+    #[test]
+    fn quiet_king_has_no_attack_units() {
+        assert_eq!(units(crate::fen::START_FEN, Color::White), 0);
+        assert_eq!(units(crate::fen::START_FEN, Color::Black), 0);
+    }
+
+    #[test]
+    fn attackers_add_up() {
+        // queen and knight bearing down on the castled king
+        let attacked = "r1b2rk1/ppp2ppp/2n5/6NQ/8/8/PPP2PPP/R1B2RK1 b - - 0 1";
+        let one_attacker = "r1b2rk1/ppp2ppp/2n5/7Q/8/8/PPP2PPP/R4RK1 b - - 0 1";
+        assert!(units(attacked, Color::Black) > 0);
+        assert_eq!(units(one_attacker, Color::Black), 0);
+    }
+
+    #[test]
+    fn safe_checks_add_units() {
+        // the rook can check from a1; a lone piece adds no attacker units, so the check is all
+        let check = "r6k/8/8/8/8/8/5PPP/6K1 w - - 0 1";
+        let covered = "r6k/8/8/8/8/8/5PPP/3R2K1 w - - 0 1";
+        assert_eq!(
+            units(check, Color::White),
+            SAFE_CHECK_UNITS[Piece::Rook.to_index()]
+        );
+        assert_eq!(units(covered, Color::White), 0);
+    }
+
+    #[test]
+    fn shield_and_open_files() {
+        let board = Board::from_str("6k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1").unwrap();
+        let mut shield = 0;
+        let mut open = 0;
+        king_safety(&board, &AttackMaps::new(&board), |term, color, count| {
+            if color == Color::White {
+                match term {
+                    Term::PawnShield(0) => shield += count,
+                    Term::KingOpenFile | Term::KingSemiOpenFile => open += count,
+                    _ => {}
+                }
+            }
+        });
+        assert_eq!(shield, 3);
+        assert_eq!(open, 0);
+    }
+}
@@ -0,0 +1,50 @@
+//! Rusty, a traditional alpha-beta chess engine.
+//!
+//! [`Engine`] is the entry point: configure it with [`EngineConfig`], give it a [`Position`]
+//! and [`Engine::search`] it within [`SearchLimits`]. Boards and moves are the types of the
+//! [`chess`] crate, which is re-exported so callers use the same version.
+
+mod api;
+mod attacks;
+mod bench;
+mod datagen;
+mod engine;
+mod fen;
+mod king;
+mod mate;
+mod minors;
+mod mobility;
+mod nnue;
+mod pawns;
+mod perft;
+mod pgn;
+mod position;
+mod san;
+mod score;
+mod threats;
+mod trace;
+mod tune;
+mod weights;
+
+pub use api::{
+    best_move, best_move_infinite_thread, best_move_with_time, Engine, EngineConfig, IterationInfo,
+    SearchEvent, SearchLimits, SearchObserver, SearchResult, DEFAULT_MOVETIME,
+};
+pub use chess;
+pub use mate::{find_mate, find_mate_checks_only};
+pub use nnue::Network;
+pub use pgn::{read_pgn, write_pgn, Game, GameNode};
+pub use position::{outcome, Outcome, Position};
+pub use san::{parse_san, to_san};
+pub use score::Score;
+pub use weights::EvalParams;
+
+// the developer commands of the binary, not part of the stable API
+#[doc(hidden)]
+pub mod tools {
+    pub use crate::bench::{bench, BENCH_DEPTH};
+    pub use crate::datagen::{datagen, DatagenOptions};
+    pub use crate::perft::{print_divide, print_perft};
+    pub use crate::trace::eval_table;
+    pub use crate::tune::tune;
+}
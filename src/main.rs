@@ -1,78 +1,138 @@
-extern crate core;
-
-mod engine;
+mod analyze;
+mod cli;
+mod play;
 mod uci;
-mod weights;
+mod xboard;
 
-use crate::engine::{best_move, evaluation_middlegame, Searcher, best_move_with_time, best_move_infinite_thread};
-use chess::Board;
-use std::str::FromStr;
-use std::time::{Duration, Instant};
-use std::thread;
-use std::sync::mpsc::{channel, Sender, Receiver};
+use cli::Command;
+use rusty::tools;
+use rusty::{Engine, EngineConfig, EvalParams, IterationInfo, Network, SearchObserver};
+use std::sync::Arc;
 
-fn debugging(fen: &str) {
-    let board = Board::from_str(fen).expect("Invalid FEN");
-    println!(
-        "{:?}, {:?}, {:?}",
-        evaluation_middlegame(&board),
-        best_move(&board, 3).0.to_string(),
-        Searcher::new(&board, 3).alpha_beta(-10000, 10000)
-    );
-}
+// exit codes: 1 when a command fails, 2 when it was not understood
+const FAILURE: i32 = 1;
+const USAGE: i32 = 2;
 
-fn bench() {
-    let board = Board::default();
-    let mut searcher = Searcher::new(&board, 1);
-    searcher.alpha_beta_with_time(Duration::from_secs(2));
-    println!("{}", searcher.depth)
+fn or_exit<T>(result: Result<T, String>) -> T {
+    result.unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(FAILURE);
+    })
 }
 
-fn test_infinite() {
-    let board = Board::default();
-    let (tx, rx) = channel();
-    let (tx2, rx2) = channel();
-    let mut thread = thread::spawn(move || {
-        let mut searcher = Searcher::new(&board, 1);
-        searcher.alpha_beta_until_stopped(rx);
-        tx2.send( searcher.depth).unwrap();
-    });
-
-    thread::sleep(Duration::from_secs(10));
-    tx.send(true).unwrap();
-    println!("{}", rx2.recv().unwrap());
+// the first line on stdin picks the protocol: "xboard" for CECP, anything else for UCI
+fn protocol(config: EngineConfig) {
+    let mut first = String::new();
+    if !matches!(std::io::stdin().read_line(&mut first), Ok(n) if n > 0) {
+        return;
+    }
+    if first.trim() == "xboard" {
+        xboard::main(config, &first);
+    } else {
+        uci::main(config, &first);
+    }
 }
 
-fn find_time_increase_per_depth(){
-    let board = Board::default();
-
-    let mut results : Vec<(u64, f64)> = vec![];
-    for i in 1..=10 {
-        // multiple trials for accuracy
-        let mut results_for_depth : Vec<f64> = vec![];
-        for a in 1..50 {
-            let mut searcher = Searcher::new(&board, 1);
-            searcher.depth = i;
-            let time = Instant::now();
-            searcher.alpha_beta(i32::MIN, i32::MAX);
-            let elapsed = time.elapsed();
-            results_for_depth.push((elapsed.as_millis() as f64));
-            println!("Depth: {}, Time: {:?}", i, elapsed);
-        }
-        // average
-        let mut sum : f64 = 0_f64;
-        for time in &results_for_depth {
-            sum += *time as f64;
-        }
-        let avg = sum / results_for_depth.len() as f64;
-        results.push((i as u64, avg));
-        println!("Depth: {}, Avg Time: {}", i, avg);
+// prints each finished iteration of `search`
+struct Progress;
 
+impl SearchObserver for Progress {
+    fn iteration(&mut self, info: &IterationInfo) {
+        let pv: Vec<String> = info.pv.iter().map(|m| m.to_string()).collect();
+        println!(
+            "depth {:>2}  score {:>7}  nodes {:>10}  time {:>6} ms  pv {}",
+            info.depth,
+            info.score.to_string(),
+            info.nodes,
+            info.elapsed.as_millis(),
+            pv.join(" ")
+        );
     }
-    println!("{:?}", results);
 }
 
-
 fn main() {
-    uci::main();
+    let cli = cli::parse(std::env::args().skip(1).collect()).unwrap_or_else(|error| {
+        eprintln!("{}\nrun `rusty help` for the commands", error);
+        std::process::exit(USAGE);
+    });
+    // --eval-file replaces the evaluation weights, --nnue evaluates with a network instead
+    let params = match &cli.weights {
+        Some(path) => {
+            let (params, warnings) = or_exit(EvalParams::load(path));
+            for warning in warnings {
+                eprintln!("warning: {}", warning);
+            }
+            params
+        }
+        None => EvalParams::default(),
+    };
+    let network = cli
+        .nnue
+        .as_ref()
+        .map(|path| Arc::new(or_exit(Network::load(path))));
+    let config = EngineConfig { params, network };
+    match cli.command {
+        Command::Protocol => protocol(config),
+        Command::Uci => uci::main(config, ""),
+        Command::Xboard => xboard::main(config, ""),
+        Command::Help => println!("{}", cli::USAGE),
+        Command::Play(args) => or_exit(play::main(&args, config)),
+        Command::Search { position, limits } => {
+            let mut engine = Engine::new(config);
+            engine.set_position(position);
+            match engine.search_with(limits, &mut Progress).best_move {
+                Some(m) => println!("bestmove {}", m),
+                None => println!("bestmove (none), the game is over"),
+            }
+        }
+        Command::Eval(position) => {
+            for line in tools::eval_table(&position.board, &config.params) {
+                println!("{}", line);
+            }
+            if let Some(network) = &config.network {
+                if position.board.status() == rusty::chess::BoardStatus::Ongoing {
+                    let eval = network.evaluate_board(&position.board);
+                    println!("NNUE evaluation: {:+.2} (white side)", eval as f64 / 100.0);
+                }
+            }
+        }
+        Command::Analyze {
+            path,
+            limits,
+            output,
+        } => {
+            let mut engine = Engine::new(config);
+            or_exit(analyze::analyze(
+                &path,
+                limits,
+                output.as_deref(),
+                &mut engine,
+            ))
+        }
+        Command::Bench(depth) => {
+            tools::bench(depth, &config.params, config.network);
+        }
+        Command::Perft {
+            depth,
+            position,
+            divide,
+        } => {
+            if divide {
+                tools::print_divide(&position.board, depth);
+            } else {
+                tools::print_perft(&position.board, depth);
+            }
+        }
+        Command::Tune {
+            path,
+            iterations,
+            output,
+        } => or_exit(tools::tune(&path, &config.params, iterations, &output)),
+        Command::Datagen { options, output } => or_exit(tools::datagen(
+            options,
+            &output,
+            &config.params,
+            config.network,
+        )),
+    }
 }
@@ -0,0 +1,209 @@
+use chess::{Board, BoardStatus, ChessMove, MoveGen, EMPTY};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::score::Score;
+
+// dedicated search for a forced mate, independent of the evaluation
+
+// the longest mate a score can report
+pub(crate) const MAX_MATE_MOVES: u8 = (Score::MAX_PLY / 2) as u8;
+
+pub(crate) struct MateSearcher {
+    board: Board,
+    pub(crate) moves: u8,
+    pub(crate) checks_only: bool,
+    pub(crate) nodes: u64,
+    // the search gives up without a mate once it has searched this many nodes
+    pub(crate) max_nodes: u64,
+    // or once this is set
+    pub(crate) stop: Option<Arc<AtomicBool>>,
+}
+
+impl MateSearcher {
+    pub(crate) fn new(board: &Board, moves: u8) -> MateSearcher {
+        MateSearcher {
+            board: *board,
+            moves: moves.min(MAX_MATE_MOVES),
+            checks_only: false,
+            nodes: 0,
+            max_nodes: u64::MAX,
+            stop: None,
+        }
+    }
+
+    // returns the shortest forced mating line of at most `moves` moves for the side to move
+    pub(crate) fn search(&mut self) -> Option<Vec<ChessMove>> {
+        let board = self.board;
+        for n in 1..=self.moves {
+            let line = self.attack(&board, n);
+            if self.is_stopped() {
+                return None;
+            }
+            if line.is_some() {
+                return line;
+            }
+        }
+        None
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.nodes >= self.max_nodes
+            || self
+                .stop
+                .as_ref()
+                .is_some_and(|stop| stop.load(Ordering::Relaxed))
+    }
+
+    // the attacker has `n` moves left to deliver mate and plays the quickest one
+    fn attack(&mut self, board: &Board, mut n: u8) -> Option<Vec<ChessMove>> {
+        let mut shortest: Option<Vec<ChessMove>> = None;
+        for m in self.attacking_moves(board) {
+            if self.is_stopped() {
+                return None;
+            }
+            let next = board.make_move_new(m);
+            self.nodes += 1;
+            match next.status() {
+                BoardStatus::Checkmate => return Some(vec![m]),
+                BoardStatus::Stalemate => continue,
+                BoardStatus::Ongoing => {}
+            }
+            if n > 1 {
+                if let Some(mut line) = self.defend(&next, n - 1) {
+                    line.insert(0, m);
+                    // a mate in k moves takes 2k - 1 plies; the other moves only matter if
+                    // they mate in fewer
+                    n = (line.len() / 2) as u8;
+                    shortest = Some(line);
+                }
+            }
+        }
+        shortest
+    }
+
+    // every defence has to run into a mate; the longest one is the principal variation
+    fn defend(&mut self, board: &Board, n: u8) -> Option<Vec<ChessMove>> {
+        let mut longest: Option<Vec<ChessMove>> = None;
+        for m in MoveGen::new_legal(board) {
+            if self.is_stopped() {
+                return None;
+            }
+            let next = board.make_move_new(m);
+            self.nodes += 1;
+            let mut line = self.attack(&next, n)?;
+            line.insert(0, m);
+            if longest.as_ref().is_none_or(|l| line.len() > l.len()) {
+                longest = Some(line);
+            }
+        }
+        longest
+    }
+
+    // checks first, then everything else unless restricted to checks
+    fn attacking_moves(&self, board: &Board) -> Vec<ChessMove> {
+        let mut checks = Vec::new();
+        let mut quiets = Vec::new();
+        for m in MoveGen::new_legal(board) {
+            if *board.make_move_new(m).checkers() != EMPTY {
+                checks.push(m);
+            } else if !self.checks_only {
+                quiets.push(m);
+            }
+        }
+        checks.append(&mut quiets);
+        checks
+    }
+}
+
+pub fn find_mate(board: &Board, moves: u8) -> Option<Vec<ChessMove>> {
+    let mut searcher = MateSearcher::new(board, moves);
+    searcher.search()
+}
+
+pub fn find_mate_checks_only(board: &Board, moves: u8) -> Option<Vec<ChessMove>> {
+    let mut searcher = MateSearcher::new(board, moves);
+    searcher.checks_only = true;
+    searcher.search()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn board(fen: &str) -> Board {
+        Board::from_str(fen).unwrap()
+    }
+
+    fn line(moves: &[ChessMove]) -> Vec<String> {
+        moves.iter().map(|m| m.to_string()).collect()
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        let back_rank = board("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1");
+        assert_eq!(line(&find_mate(&back_rank, 1).unwrap()), ["a1a8"]);
+        assert_eq!(
+            line(&find_mate_checks_only(&back_rank, 1).unwrap()),
+            ["a1a8"]
+        );
+    }
+
+    #[test]
+    fn finds_the_shortest_line() {
+        // 1. Ra6 bxa6 2. b7#, and nothing faster
+        let position = board("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1");
+        assert_eq!(find_mate(&position, 1), None);
+        let mate = find_mate(&position, 2).unwrap();
+        assert_eq!(line(&mate), ["a1a6", "b7a6", "b6b7"]);
+        assert_eq!(find_mate(&position, 5), Some(mate));
+        // Ra8 mates at once although Re8 and slower mates are there too
+        let position = board("6k1/5ppp/8/8/8/8/8/R3R1K1 w - - 0 1");
+        assert_eq!(find_mate(&position, 3).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn attacker_takes_the_quickest_mate() {
+        // the knight checks come first and 1. Nf7+ Kg8 2. Ra8# mates too, but Ra8 mates at once
+        let position = board("7k/6pp/8/4N3/2B5/8/8/R5K1 w - - 0 1");
+        let mut searcher = MateSearcher::new(&position, 2);
+        assert_eq!(line(&searcher.attack(&position, 2).unwrap()), ["a1a8"]);
+    }
+
+    #[test]
+    fn defender_takes_the_longest_defence() {
+        // 1. Ra6 f6 2. Bxf6+ Rg7 3. Rxa8#; 1... f6 holds out longest
+        let position = board("r5rk/5p1p/5R2/4B3/8/8/7P/7K w - - 0 1");
+        assert_eq!(find_mate(&position, 2), None);
+        let mate = find_mate(&position, 3).unwrap();
+        assert_eq!(mate.len(), 5);
+        assert_eq!(mate[0].to_string(), "f6a6");
+    }
+
+    #[test]
+    fn no_mate_within_the_limit() {
+        assert_eq!(find_mate(&Board::default(), 2), None);
+        // the only mate starts with a quiet move
+        let position = board("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1");
+        assert_eq!(find_mate_checks_only(&position, 3), None);
+        // black threatens mate, white has none of its own
+        let position = board("r5k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1");
+        assert_eq!(find_mate(&position, 3), None);
+    }
+
+    #[test]
+    fn gives_up_at_the_node_limit_or_when_stopped() {
+        let position = board("r5rk/5p1p/5R2/4B3/8/8/7P/7K w - - 0 1");
+        let mut searcher = MateSearcher::new(&position, 3);
+        searcher.max_nodes = 100;
+        assert_eq!(searcher.search(), None);
+        assert!(searcher.nodes <= 100);
+        let mut searcher = MateSearcher::new(&position, 3);
+        searcher.stop = Some(Arc::new(AtomicBool::new(true)));
+        assert_eq!(searcher.search(), None);
+        assert_eq!(searcher.nodes, 0);
+        // a mate longer than a score can report is not searched for
+        assert_eq!(MateSearcher::new(&position, u8::MAX).moves, MAX_MATE_MOVES);
+    }
+}
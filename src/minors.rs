@@ -0,0 +1,184 @@
+use chess::{BitBoard, Board, Color, Piece, Rank, Square, EMPTY};
+
+use crate::attacks::AttackMaps;
+use crate::pawns::{attack_span, relative_rank};
+use crate::weights::Term;
+
+// Knights and bishops beyond their material value, plus the patterns where a bishop or rook
+// has walled itself in.
+
+const LIGHT_SQUARES: BitBoard = BitBoard(0x55AA_55AA_55AA_55AA);
+
+// the square as seen from the side's own end of the board
+fn relative(square: Square, color: Color) -> Square {
+    if color == Color::White {
+        square
+    } else {
+        Square::make_square(
+            Rank::from_index(7 - square.get_rank().to_index()),
+            square.get_file(),
+        )
+    }
+}
+
+fn distance(a: Square, b: Square) -> usize {
+    let ranks = a.get_rank().to_index().abs_diff(b.get_rank().to_index());
+    let files = a.get_file().to_index().abs_diff(b.get_file().to_index());
+    ranks.max(files)
+}
+
+// on the fourth to sixth rank, defended by a pawn and out of reach of the enemy pawns
+fn is_outpost(square: Square, color: Color, maps: &AttackMaps, enemy_pawns: BitBoard) -> bool {
+    (3..=5).contains(&relative_rank(square, color))
+        && maps.pieces(color, Piece::Pawn) & BitBoard::from_square(square) != EMPTY
+        && attack_span(square, color) & enemy_pawns == EMPTY
+}
+
+pub(crate) fn minor_pieces(
+    board: &Board,
+    maps: &AttackMaps,
+    mut add: impl FnMut(Term, Color, i32),
+) {
+    let pawns = *board.pieces(Piece::Pawn);
+    for color in [Color::White, Color::Black] {
+        let ours = *board.color_combined(color);
+        let own_pawns = pawns & ours;
+        let enemy_pawns = pawns & *board.color_combined(!color);
+        let enemy_king = board.king_square(!color);
+        let bishops = *board.pieces(Piece::Bishop) & ours;
+
+        if bishops.popcnt() >= 2 {
+            add(Term::BishopPair, color, 1);
+        }
+
+        for piece in [Piece::Knight, Piece::Bishop] {
+            for square in *board.pieces(piece) & ours {
+                if is_outpost(square, color, maps, enemy_pawns) {
+                    add(Term::Outpost(piece), color, 1);
+                }
+            }
+        }
+
+        for square in *board.pieces(Piece::Knight) & ours {
+            add(
+                Term::KnightTropism,
+                color,
+                7 - distance(square, enemy_king) as i32,
+            );
+        }
+
+        for square in bishops {
+            // own pawns on the bishop's colour get in its way
+            let same_color = if LIGHT_SQUARES & BitBoard::from_square(square) != EMPTY {
+                LIGHT_SQUARES
+            } else {
+                !LIGHT_SQUARES
+            };
+            add(
+                Term::BadBishop,
+                color,
+                (own_pawns & same_color).popcnt() as i32,
+            );
+
+            // Bxa7 b6: the bishop is cut off behind the enemy pawns
+            let trapped = match relative(square, color) {
+                Square::A7 => Some(Square::B6),
+                Square::H7 => Some(Square::G6),
+                Square::A6 => Some(Square::B5),
+                Square::H6 => Some(Square::G5),
+                _ => None,
+            };
+            if let Some(pawn) = trapped {
+                if enemy_pawns & BitBoard::from_square(relative(pawn, color)) != EMPTY {
+                    add(Term::TrappedBishop, color, 1);
+                }
+            }
+        }
+
+        // a king that walked towards a corner without castling boxes in the rook behind it
+        let king = relative(board.king_square(color), color);
+        let rights = board.castle_rights(color);
+        for square in *board.pieces(Piece::Rook) & ours {
+            let rook = relative(square, color);
+            let boxed = match king {
+                Square::F1 | Square::G1 => {
+                    !rights.has_kingside()
+                        && matches!(rook, Square::G1 | Square::H1 | Square::H2)
+                        && rook.get_file() > king.get_file()
+                }
+                Square::B1 | Square::C1 | Square::D1 => {
+                    !rights.has_queenside()
+                        && matches!(rook, Square::A1 | Square::B1 | Square::A2)
+                        && rook.get_file() < king.get_file()
+                }
+                _ => false,
+            };
+            if boxed {
+                add(Term::TrappedRook, color, 1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn count(fen: &str, wanted: Term, color: Color) -> i32 {
+        let board = Board::from_str(fen).unwrap();
+        let maps = AttackMaps::new(&board);
+        let mut total = 0;
+        minor_pieces(&board, &maps, |term, c, count| {
+            if term == wanted && c == color {
+                total += count;
+            }
+        });
+        total
+    }
+
+    #[test]
+    fn bishop_pair_counts_for_its_owner() {
+        let fen = "2b1kb2/8/8/8/8/8/8/4K1N1 w - - 0 1";
+        assert_eq!(count(fen, Term::BishopPair, Color::Black), 1);
+        assert_eq!(count(fen, Term::BishopPair, Color::White), 0);
+    }
+
+    #[test]
+    fn knight_outpost() {
+        // e5 is defended by d4 and no black pawn can ever attack it
+        let fen = "4k3/p7/8/4N3/3P4/8/8/4K3 w - - 0 1";
+        assert_eq!(count(fen, Term::Outpost(Piece::Knight), Color::White), 1);
+        // with a pawn on f7 it can be chased away
+        let fen = "4k3/5p2/8/4N3/3P4/8/8/4K3 w - - 0 1";
+        assert_eq!(count(fen, Term::Outpost(Piece::Knight), Color::White), 0);
+    }
+
+    #[test]
+    fn trapped_bishop_mirrored() {
+        assert_eq!(
+            count(
+                "4k3/B7/1p6/8/8/8/8/4K3 w - - 0 1",
+                Term::TrappedBishop,
+                Color::White
+            ),
+            1
+        );
+        assert_eq!(
+            count(
+                "4k3/8/8/8/8/1P6/b7/4K3 b - - 0 1",
+                Term::TrappedBishop,
+                Color::Black
+            ),
+            1
+        );
+    }
+
+    #[test]
+    fn rook_boxed_in_by_king() {
+        let fen = "4k3/8/8/8/8/8/5PPP/5K1R w - - 0 1";
+        assert_eq!(count(fen, Term::TrappedRook, Color::White), 1);
+        let fen = "4k3/8/8/8/8/8/5PPP/4K2R w K - 0 1";
+        assert_eq!(count(fen, Term::TrappedRook, Color::White), 0);
+    }
+}
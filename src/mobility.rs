@@ -0,0 +1,66 @@
+use chess::{Board, Color, Piece};
+
+use crate::attacks::{piece_attacks, AttackMaps};
+use crate::weights::Term;
+
+// Mobility: the squares each knight, bishop, rook and queen can go to without stepping onto
+// its own pieces or into the reach of an enemy pawn. Each count has its own middlegame and
+// endgame weight, since the first few squares matter much more than the last ones.
+
+pub(crate) const MOBILITY_PIECES: [Piece; 4] =
+    [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen];
+
+pub(crate) fn mobility(board: &Board, maps: &AttackMaps, mut add: impl FnMut(Term, Color, i32)) {
+    for color in [Color::White, Color::Black] {
+        let available = !board.color_combined(color) & !maps.pieces(!color, Piece::Pawn);
+        for piece in MOBILITY_PIECES {
+            for square in *board.pieces(piece) & *board.color_combined(color) {
+                let attacks = piece_attacks(piece, square, color, *board.combined());
+                let count = (attacks & available).popcnt() as usize;
+                add(Term::Mobility(piece, count), color, 1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn counts(fen: &str, color: Color) -> Vec<(Piece, usize)> {
+        let board = Board::from_str(fen).unwrap();
+        let maps = AttackMaps::new(&board);
+        let mut counts = Vec::new();
+        mobility(&board, &maps, |term, c, _| {
+            if let Term::Mobility(piece, count) = term {
+                if c == color {
+                    counts.push((piece, count));
+                }
+            }
+        });
+        counts
+    }
+
+    #[test]
+    fn squares_covered_by_enemy_pawns_do_not_count() {
+        // the knight on e4 has eight squares, but d6 and f6 are covered by the e7 pawn
+        let counts = counts("4k3/4p3/8/8/4N3/8/8/4K3 w - - 0 1", Color::White);
+        assert_eq!(counts, vec![(Piece::Knight, 6)]);
+    }
+
+    #[test]
+    fn own_pieces_block() {
+        let counts = counts(crate::fen::START_FEN, Color::White);
+        let knights: Vec<usize> = counts
+            .iter()
+            .filter(|(p, _)| *p == Piece::Knight)
+            .map(|(_, c)| *c)
+            .collect();
+        assert_eq!(knights, vec![2, 2]);
+        assert!(counts
+            .iter()
+            .filter(|(p, _)| *p != Piece::Knight)
+            .all(|(_, c)| *c == 0));
+    }
+}
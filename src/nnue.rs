@@ -0,0 +1,413 @@
+use chess::{Board, Color, Piece, Square, ALL_PIECES};
+use std::fs;
+
+// Efficiently updatable neural network evaluation. The first layer sums a column of weights
+// for every (king square, piece, square) feature that is present, one accumulator per side
+// seen from that side's own king. Moves change only a handful of features, so a child's
+// accumulators are the parent's plus and minus a few columns; only a king move forces that
+// side to be rebuilt. The accumulators then go through a clipped ReLU into a single output.
+//
+// Network file, all values little-endian:
+//   "RSNN", version u32 (1), feature set u32 (0 = HalfKP, 1 = HalfKA), hidden size u32,
+//   feature weights i16 [inputs][hidden], feature biases i16 [hidden],
+//   output weights i16 [2 * hidden] (side to move first), output bias i32
+
+const MAGIC: &[u8; 4] = b"RSNN";
+const VERSION: u32 = 1;
+
+// quantisation of the accumulator and the output weights, and the centipawn scale
+const QA: i32 = 255;
+const QB: i32 = 64;
+const SCALE: i32 = 400;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FeatureSet {
+    // king square x the ten non-king pieces x square
+    HalfKP,
+    // king square x all twelve pieces x square
+    HalfKA,
+}
+
+impl FeatureSet {
+    fn pieces(&self) -> usize {
+        match self {
+            FeatureSet::HalfKP => 10,
+            FeatureSet::HalfKA => 12,
+        }
+    }
+
+    fn inputs(&self) -> usize {
+        64 * self.pieces() * 64
+    }
+
+    // the feature of `piece` of `color` on `square`, seen by `perspective` with its king on
+    // `king`; HalfKP has no features for kings
+    fn index(
+        &self,
+        perspective: Color,
+        king: Square,
+        piece: Piece,
+        color: Color,
+        square: Square,
+    ) -> Option<usize> {
+        if *self == FeatureSet::HalfKP && piece == Piece::King {
+            return None;
+        }
+        let orient = |square: Square| {
+            if perspective == Color::White {
+                square.to_index()
+            } else {
+                square.to_index() ^ 56
+            }
+        };
+        let kind = piece.to_index() * 2 + (color != perspective) as usize;
+        Some((orient(king) * self.pieces() + kind) * 64 + orient(square))
+    }
+}
+
+pub struct Network {
+    features: FeatureSet,
+    hidden: usize,
+    feature_weights: Vec<i16>,
+    feature_biases: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+fn read_u32(bytes: &[u8], offset: &mut usize) -> Result<u32, String> {
+    let value = bytes
+        .get(*offset..*offset + 4)
+        .ok_or("network file is truncated")?;
+    *offset += 4;
+    Ok(u32::from_le_bytes(value.try_into().unwrap()))
+}
+
+fn read_i16s(bytes: &[u8], offset: &mut usize, count: usize) -> Result<Vec<i16>, String> {
+    let values = bytes
+        .get(*offset..*offset + 2 * count)
+        .ok_or("network file is truncated")?;
+    *offset += 2 * count;
+    Ok(values
+        .chunks_exact(2)
+        .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+        .collect())
+}
+
+impl Network {
+    pub fn load(path: &str) -> Result<Network, String> {
+        let bytes = fs::read(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        Network::from_bytes(&bytes).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Network, String> {
+        if bytes.len() < 4 || &bytes[..4] != MAGIC {
+            return Err(String::from("not a network file"));
+        }
+        let mut offset = 4;
+        let version = read_u32(bytes, &mut offset)?;
+        if version != VERSION {
+            return Err(format!("unsupported network version {}", version));
+        }
+        let features = match read_u32(bytes, &mut offset)? {
+            0 => FeatureSet::HalfKP,
+            1 => FeatureSet::HalfKA,
+            other => return Err(format!("unknown feature set {}", other)),
+        };
+        let hidden = read_u32(bytes, &mut offset)? as usize;
+        if hidden == 0 || hidden > 4096 {
+            return Err(format!("invalid hidden layer size {}", hidden));
+        }
+        let feature_weights = read_i16s(bytes, &mut offset, features.inputs() * hidden)?;
+        let feature_biases = read_i16s(bytes, &mut offset, hidden)?;
+        let output_weights = read_i16s(bytes, &mut offset, 2 * hidden)?;
+        let output_bias = read_u32(bytes, &mut offset)? as i32;
+        if offset != bytes.len() {
+            return Err(format!(
+                "{} unexpected bytes at the end",
+                bytes.len() - offset
+            ));
+        }
+        Ok(Network {
+            features,
+            hidden,
+            feature_weights,
+            feature_biases,
+            output_weights,
+            output_bias,
+        })
+    }
+
+    #[cfg(test)]
+    pub(crate) fn feature_set(&self) -> FeatureSet {
+        self.features
+    }
+
+    fn column(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * self.hidden..(feature + 1) * self.hidden]
+    }
+
+    // score in centipawns from white's point of view, building the accumulators from scratch
+    pub fn evaluate_board(&self, board: &Board) -> i32 {
+        let eval = self.evaluate(&Accumulator::new(self, board), board.side_to_move());
+        if board.side_to_move() == Color::White {
+            eval
+        } else {
+            -eval
+        }
+    }
+
+    // score in centipawns from the side to move's point of view
+    pub(crate) fn evaluate(&self, accumulator: &Accumulator, side_to_move: Color) -> i32 {
+        let us = &accumulator.values[side_to_move.to_index()];
+        let them = &accumulator.values[(!side_to_move).to_index()];
+        let (our_weights, their_weights) = self.output_weights.split_at(self.hidden);
+        let sum = output(us, our_weights) + output(them, their_weights);
+        // at most 2 * 4096 * 255 * 32768 plus the bias, which scales back into an i32
+        ((sum + self.output_bias as i64) * SCALE as i64 / (QA * QB) as i64) as i32
+    }
+}
+
+// i64, as a large hidden layer of large weights overflows an i32
+fn output(accumulator: &[i16], weights: &[i16]) -> i64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if accumulator.len().is_multiple_of(16) && is_x86_feature_detected!("avx2") {
+            // safe: the CPU supports AVX2 and the length is a multiple of the vector width
+            return unsafe { output_avx2(accumulator, weights) };
+        }
+    }
+    output_scalar(accumulator, weights)
+}
+
+fn output_scalar(accumulator: &[i16], weights: &[i16]) -> i64 {
+    accumulator
+        .iter()
+        .zip(weights)
+        .map(|(&a, &w)| ((a as i32).clamp(0, QA) * w as i32) as i64)
+        .sum()
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn output_avx2(accumulator: &[i16], weights: &[i16]) -> i64 {
+    use std::arch::x86_64::*;
+    let zero = _mm256_setzero_si256();
+    let max = _mm256_set1_epi16(QA as i16);
+    let mut sum = _mm256_setzero_si256();
+    for i in (0..accumulator.len()).step_by(16) {
+        let a = _mm256_loadu_si256(accumulator.as_ptr().add(i) as *const __m256i);
+        let w = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+        let clipped = _mm256_min_epi16(_mm256_max_epi16(a, zero), max);
+        // a pair of products fits an i32 lane; the running sum is kept in i64 lanes
+        let pairs = _mm256_madd_epi16(clipped, w);
+        sum = _mm256_add_epi64(sum, _mm256_cvtepi32_epi64(_mm256_castsi256_si128(pairs)));
+        sum = _mm256_add_epi64(
+            sum,
+            _mm256_cvtepi32_epi64(_mm256_extracti128_si256::<1>(pairs)),
+        );
+    }
+    let mut lanes = [0i64; 4];
+    _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, sum);
+    lanes.iter().sum()
+}
+
+// first-layer sums for white's and black's point of view
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Accumulator {
+    values: [Vec<i16>; 2],
+}
+
+impl Accumulator {
+    pub(crate) fn new(network: &Network, board: &Board) -> Accumulator {
+        Accumulator {
+            values: [
+                Accumulator::refresh(network, board, Color::White),
+                Accumulator::refresh(network, board, Color::Black),
+            ],
+        }
+    }
+
+    fn refresh(network: &Network, board: &Board, perspective: Color) -> Vec<i16> {
+        let mut values = network.feature_biases.clone();
+        let king = board.king_square(perspective);
+        for piece in ALL_PIECES {
+            for color in [Color::White, Color::Black] {
+                for square in *board.pieces(piece) & *board.color_combined(color) {
+                    if let Some(feature) =
+                        network
+                            .features
+                            .index(perspective, king, piece, color, square)
+                    {
+                        add(&mut values, network.column(feature));
+                    }
+                }
+            }
+        }
+        values
+    }
+
+    // the accumulators after the move from `before` to `after`
+    pub(crate) fn update(&self, network: &Network, before: &Board, after: &Board) -> Accumulator {
+        let mut next = self.clone();
+        for perspective in [Color::White, Color::Black] {
+            let king = after.king_square(perspective);
+            let values = &mut next.values[perspective.to_index()];
+            if king != before.king_square(perspective) {
+                *values = Accumulator::refresh(network, after, perspective);
+                continue;
+            }
+            for piece in ALL_PIECES {
+                for color in [Color::White, Color::Black] {
+                    let old = *before.pieces(piece) & *before.color_combined(color);
+                    let new = *after.pieces(piece) & *after.color_combined(color);
+                    for square in old & !new {
+                        if let Some(feature) =
+                            network
+                                .features
+                                .index(perspective, king, piece, color, square)
+                        {
+                            subtract(values, network.column(feature));
+                        }
+                    }
+                    for square in new & !old {
+                        if let Some(feature) =
+                            network
+                                .features
+                                .index(perspective, king, piece, color, square)
+                        {
+                            add(values, network.column(feature));
+                        }
+                    }
+                }
+            }
+        }
+        next
+    }
+}
+
+// plain loops the compiler vectorises on its own
+fn add(values: &mut [i16], column: &[i16]) {
+    for (v, w) in values.iter_mut().zip(column) {
+        *v = v.wrapping_add(*w);
+    }
+}
+
+fn subtract(values: &mut [i16], column: &[i16]) {
+    for (v, w) in values.iter_mut().zip(column) {
+        *v = v.wrapping_sub(*w);
+    }
+}
+
+// a small network with pseudo-random weights, serialised the way a trainer would write it
+#[cfg(test)]
+pub(crate) fn network_bytes(features: FeatureSet, hidden: usize) -> Vec<u8> {
+    let mut state: u32 = 12345;
+    let mut next = move || {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        ((state >> 16) % 200) as i16 - 100
+    };
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(VERSION.to_le_bytes());
+    let set: u32 = if features == FeatureSet::HalfKP { 0 } else { 1 };
+    bytes.extend(set.to_le_bytes());
+    bytes.extend((hidden as u32).to_le_bytes());
+    for _ in 0..(features.inputs() + 1 + 2) * hidden {
+        bytes.extend(next().to_le_bytes());
+    }
+    bytes.extend(37i32.to_le_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::ChessMove;
+    use std::str::FromStr;
+
+    fn network(features: FeatureSet) -> Network {
+        Network::from_bytes(&network_bytes(features, 32)).unwrap()
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let bytes = network_bytes(FeatureSet::HalfKP, 16);
+        assert!(Network::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(Network::from_bytes(&extra).is_err());
+        assert!(Network::from_bytes(b"NOPE").is_err());
+        assert_eq!(
+            Network::from_bytes(&bytes).unwrap().feature_set(),
+            FeatureSet::HalfKP
+        );
+    }
+
+    #[test]
+    fn incremental_updates_match_a_refresh() {
+        // castling both ways, an en passant capture, a promotion with capture and king moves
+        let moves = [
+            "e2e4", "d7d5", "e4e5", "f7f5", "e5f6", "c8e6", "g1f3", "b8c6", "f1c4", "d8d7", "e1g1",
+            "e8c8", "f6g7", "h7h6", "g7h8q", "e6h3", "d2d3", "c8b8", "g1h1",
+        ];
+        for features in [FeatureSet::HalfKP, FeatureSet::HalfKA] {
+            let network = network(features);
+            let mut board = Board::default();
+            let mut accumulator = Accumulator::new(&network, &board);
+            for m in moves {
+                let m = ChessMove::from_str(m).unwrap();
+                assert!(board.legal(m), "{} in {}", m, board);
+                let next = board.make_move_new(m);
+                accumulator = accumulator.update(&network, &board, &next);
+                board = next;
+                assert_eq!(
+                    accumulator,
+                    Accumulator::new(&network, &board),
+                    "after {}",
+                    m
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn vector_output_matches_scalar() {
+        let network = network(FeatureSet::HalfKA);
+        let board =
+            Board::from_str("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let accumulator = Accumulator::new(&network, &board);
+        for side in [Color::White, Color::Black] {
+            let values = &accumulator.values[side.to_index()];
+            assert_eq!(
+                output(values, &network.output_weights[..network.hidden]),
+                output_scalar(values, &network.output_weights[..network.hidden])
+            );
+        }
+    }
+
+    #[test]
+    fn large_networks_do_not_overflow() {
+        // every hidden unit saturated against the largest weight
+        let hidden = 4096;
+        let network = Network {
+            features: FeatureSet::HalfKP,
+            hidden,
+            feature_weights: Vec::new(),
+            feature_biases: Vec::new(),
+            output_weights: vec![i16::MAX; 2 * hidden],
+            output_bias: i32::MAX,
+        };
+        let accumulator = Accumulator {
+            values: [vec![i16::MAX; hidden], vec![i16::MAX; hidden]],
+        };
+        let weights = &network.output_weights[..hidden];
+        let sum = hidden as i64 * QA as i64 * i16::MAX as i64;
+        assert_eq!(output(&accumulator.values[0], weights), sum);
+        assert_eq!(output_scalar(&accumulator.values[0], weights), sum);
+        let expected = (2 * sum + i32::MAX as i64) * SCALE as i64 / (QA * QB) as i64;
+        assert_eq!(
+            network.evaluate(&accumulator, Color::White) as i64,
+            expected
+        );
+    }
+}
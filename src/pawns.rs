@@ -0,0 +1,302 @@
+use chess::{
+    get_adjacent_files, get_file, get_pawn_attacks, BitBoard, Board, Color, Piece, Square,
+};
+use std::cell::RefCell;
+
+use crate::weights::Term;
+
+// Pawn structure. It depends on nothing but the pawns, so the counts for a pawn configuration
+// are computed once and cached in a table keyed on a pawn-only Zobrist key; only the weighting
+// is redone for every position.
+
+const CENTER: BitBoard = BitBoard(0x0000001818000000);
+
+// chess::Board::get_pawn_hash always returns 0 and the crate's Zobrist keys are private,
+// so the pawn keys are generated here
+const PAWN_KEYS: [[u64; 64]; 2] = pawn_keys();
+
+const fn pawn_keys() -> [[u64; 64]; 2] {
+    // splitmix64
+    let mut keys = [[0; 64]; 2];
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut i = 0;
+    while i < 128 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        keys[i / 64][i % 64] = z ^ (z >> 31);
+        i += 1;
+    }
+    keys
+}
+
+pub(crate) fn pawn_key(board: &Board) -> u64 {
+    let mut key = 0;
+    for color in [Color::White, Color::Black] {
+        for square in *board.pieces(Piece::Pawn) & *board.color_combined(color) {
+            key ^= PAWN_KEYS[color.to_index()][square.to_index()];
+        }
+    }
+    key
+}
+
+// rank counted from the side's own back rank
+pub(crate) fn relative_rank(square: Square, color: Color) -> usize {
+    let rank = square.get_rank().to_index();
+    if color == Color::White {
+        rank
+    } else {
+        7 - rank
+    }
+}
+
+// the ranks strictly in front of `rank` from the side's point of view
+fn ranks_ahead(rank: usize, color: Color) -> BitBoard {
+    if color == Color::White {
+        BitBoard(!0u64 << (8 * rank) << 8)
+    } else {
+        BitBoard((1u64 << (8 * rank)) - 1)
+    }
+}
+
+// the squares in front of a pawn on its own file
+pub(crate) fn front_span(square: Square, color: Color) -> BitBoard {
+    get_file(square.get_file()) & ranks_ahead(square.get_rank().to_index(), color)
+}
+
+// the squares in front of a pawn on the neighbouring files, which enemy pawns pass through or
+// control on their way past it
+pub(crate) fn attack_span(square: Square, color: Color) -> BitBoard {
+    get_adjacent_files(square.get_file()) & ranks_ahead(square.get_rank().to_index(), color)
+}
+
+pub(crate) fn is_passed(square: Square, color: Color, enemy_pawns: BitBoard) -> bool {
+    (front_span(square, color) | attack_span(square, color)) & enemy_pawns == BitBoard(0)
+}
+
+// per-side counts of every pawn-structure feature; passed, candidate, connected and phalanx
+// pawns are counted by relative rank because their value grows as they advance
+#[derive(Clone, Copy, Default)]
+pub(crate) struct PawnInfo {
+    key: u64,
+    pub(crate) passers: [BitBoard; 2],
+    isolated: [u8; 2],
+    doubled: [u8; 2],
+    backward: [u8; 2],
+    center: [u8; 2],
+    passed: [[u8; 8]; 2],
+    candidate: [[u8; 8]; 2],
+    connected: [[u8; 8]; 2],
+    phalanx: [[u8; 8]; 2],
+}
+
+impl PawnInfo {
+    fn new(board: &Board, key: u64) -> PawnInfo {
+        let mut info = PawnInfo {
+            key,
+            ..PawnInfo::default()
+        };
+        let pawns = *board.pieces(Piece::Pawn);
+        for color in [Color::White, Color::Black] {
+            let c = color.to_index();
+            let own = pawns & *board.color_combined(color);
+            let enemy = pawns & *board.color_combined(!color);
+            info.center[c] = (own & CENTER).popcnt() as u8;
+            for square in own {
+                let rank = relative_rank(square, color);
+                let adjacent = get_adjacent_files(square.get_file());
+                // own pawns on the neighbouring files at or behind this one, which can still
+                // come up to support it
+                let supporters = own & adjacent & !ranks_ahead(square.get_rank().to_index(), color);
+                let supported = get_pawn_attacks(square, !color, own) != BitBoard(0);
+                let phalanx = own & adjacent & chess::get_rank(square.get_rank()) != BitBoard(0);
+
+                if own & adjacent == BitBoard(0) {
+                    info.isolated[c] += 1;
+                } else if supporters == BitBoard(0) {
+                    // nothing can defend it and the square in front is held by an enemy pawn
+                    let stop = square.uforward(color);
+                    if get_pawn_attacks(stop, color, enemy) != BitBoard(0) {
+                        info.backward[c] += 1;
+                    }
+                }
+                // only the rear pawn of a doubled pair is penalised
+                if front_span(square, color) & own != BitBoard(0) {
+                    info.doubled[c] += 1;
+                }
+                if supported {
+                    info.connected[c][rank] += 1;
+                }
+                if phalanx {
+                    info.phalanx[c][rank] += 1;
+                }
+                if front_span(square, color) & (own | enemy) != BitBoard(0) {
+                    continue;
+                }
+                if is_passed(square, color, enemy) {
+                    info.passed[c][rank] += 1;
+                    info.passers[c] |= BitBoard::from_square(square);
+                } else {
+                    // on a half-open file with at least as many helpers as enemy pawns in the
+                    // way, it can force its way through
+                    let sentries = enemy & attack_span(square, color);
+                    if supporters.popcnt() >= sentries.popcnt() {
+                        info.candidate[c][rank] += 1;
+                    }
+                }
+            }
+        }
+        info
+    }
+
+    // hands every feature with its count to `add`, the way the evaluation weighs them
+    pub(crate) fn for_each_term(&self, mut add: impl FnMut(Term, Color, i32)) {
+        for color in [Color::White, Color::Black] {
+            let c = color.to_index();
+            add(Term::IsolatedPawn, color, self.isolated[c] as i32);
+            add(Term::DoubledPawn, color, self.doubled[c] as i32);
+            add(Term::BackwardPawn, color, self.backward[c] as i32);
+            add(Term::CenterControl, color, self.center[c] as i32);
+            for rank in 1..7 {
+                add(Term::PassedPawn(rank), color, self.passed[c][rank] as i32);
+                add(
+                    Term::CandidatePassedPawn(rank),
+                    color,
+                    self.candidate[c][rank] as i32,
+                );
+                add(
+                    Term::ConnectedPawn(rank),
+                    color,
+                    self.connected[c][rank] as i32,
+                );
+                add(Term::PhalanxPawn(rank), color, self.phalanx[c][rank] as i32);
+            }
+        }
+    }
+}
+
+const PAWN_TABLE_SIZE: usize = 1 << 14;
+
+// always-replace table; a board without pawns has key 0 and matches the empty entries,
+// which is also the right answer for it
+pub(crate) struct PawnTable {
+    entries: Vec<PawnInfo>,
+}
+
+impl PawnTable {
+    pub(crate) fn new() -> PawnTable {
+        PawnTable {
+            entries: vec![PawnInfo::default(); PAWN_TABLE_SIZE],
+        }
+    }
+
+    pub(crate) fn probe(&mut self, board: &Board) -> PawnInfo {
+        let key = pawn_key(board);
+        let entry = &mut self.entries[key as usize & (PAWN_TABLE_SIZE - 1)];
+        if entry.key != key {
+            *entry = PawnInfo::new(board, key);
+        }
+        *entry
+    }
+}
+
+thread_local! {
+    // one table per search thread, so probing needs no locking
+    static PAWN_TABLE: RefCell<PawnTable> = RefCell::new(PawnTable::new());
+}
+
+pub(crate) fn pawn_structure(board: &Board) -> PawnInfo {
+    PAWN_TABLE.with(|table| table.borrow_mut().probe(board))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn structure(fen: &str) -> PawnInfo {
+        PawnInfo::new(&Board::from_str(fen).unwrap(), 0)
+    }
+
+    #[test]
+    fn isolated_and_doubled() {
+        // white: a2 isolated, c2 and c3 doubled and isolated; black: h7 isolated
+        let info = structure("4k3/7p/8/8/8/2P5/P1P5/4K3 w - - 0 1");
+        assert_eq!(info.isolated, [3, 1]);
+        assert_eq!(info.doubled, [1, 0]);
+    }
+
+    #[test]
+    fn passed_by_rank() {
+        // e5 is passed; d4 has c5 in its way and c5 has d4 in its way
+        let info = structure("4k3/8/8/2p1P3/3P4/8/8/4K3 w - - 0 1");
+        assert_eq!(info.passed[0][4], 1);
+        assert_eq!(info.passed[0][3], 0);
+        assert_eq!(info.passers[0], BitBoard::from_square(Square::E5));
+        assert_eq!(info.passed[1], [0; 8]);
+        // black pawns count from black's side
+        let info = structure("4k3/8/8/8/8/4p3/8/4K3 b - - 0 1");
+        assert_eq!(info.passed[1][5], 1);
+    }
+
+    #[test]
+    fn backward() {
+        // d3 is behind both neighbours and d4 is covered by the pawn on e5
+        let info = structure("4k3/8/8/4p3/2P1P3/3P4/8/4K3 w - - 0 1");
+        assert_eq!(info.backward, [1, 0]);
+        let info = structure("4k3/8/8/8/2P1P3/3P4/8/4K3 w - - 0 1");
+        assert_eq!(info.backward, [0, 0]);
+    }
+
+    #[test]
+    fn connected_and_phalanx() {
+        let info = structure("4k3/8/8/8/3PP3/2P5/8/4K3 w - - 0 1");
+        // d4 is defended by c3, d4 and e4 stand side by side
+        assert_eq!(info.connected[0][3], 1);
+        assert_eq!(info.phalanx[0][3], 2);
+    }
+
+    #[test]
+    fn candidate() {
+        // b5 has a free file and a4 to help against the a6 pawn
+        let info = structure("4k3/8/p7/1P6/P7/8/8/4K3 w - - 0 1");
+        assert_eq!(info.candidate[0][4], 1);
+        assert_eq!(info.passed[0], [0; 8]);
+    }
+
+    #[test]
+    fn mirrored_positions_agree() {
+        let white = structure("4k3/pp3p2/2p5/3P4/1P4P1/8/P4P1P/4K3 w - - 0 1");
+        let black = structure("4k3/p4p1p/8/1p4p1/3p4/2P5/PP3P2/4K3 b - - 0 1");
+        fn swap<T: Copy>(pair: [T; 2]) -> [T; 2] {
+            [pair[1], pair[0]]
+        }
+        assert_eq!(white.isolated, swap(black.isolated));
+        assert_eq!(white.doubled, swap(black.doubled));
+        assert_eq!(white.backward, swap(black.backward));
+        assert_eq!(white.center, swap(black.center));
+        assert_eq!(white.passed, swap(black.passed));
+        assert_eq!(white.candidate, swap(black.candidate));
+        assert_eq!(white.connected, swap(black.connected));
+        assert_eq!(white.phalanx, swap(black.phalanx));
+    }
+
+    #[test]
+    fn table_returns_cached_entry() {
+        let board = Board::default();
+        let key = pawn_key(&board);
+        let slot = key as usize & (PAWN_TABLE_SIZE - 1);
+        let mut table = PawnTable::new();
+        assert_ne!(table.entries[slot].key, key);
+        let first = table.probe(&board);
+        assert_eq!(first.key, key);
+        assert_eq!(table.entries[slot].key, key);
+        // a changed entry is returned as it is, so the second probe did not recompute it
+        table.entries[slot].isolated = [7, 7];
+        assert_eq!(table.probe(&board).isolated, [7, 7]);
+        // an entry stored under another key is replaced
+        table.entries[slot].key = key ^ 1;
+        assert_eq!(table.probe(&board).isolated, first.isolated);
+    }
+}
@@ -0,0 +1,137 @@
+use chess::{Board, ChessMove, MoveGen};
+use std::time::Instant;
+
+// move generation and make-move verification by counting leaf nodes
+
+pub fn perft(board: &Board, depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = MoveGen::new_legal(board);
+    // bulk counting: the number of legal moves is the number of leaves one ply down
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    let mut nodes = 0;
+    for m in moves {
+        nodes += perft(&board.make_move_new(m), depth - 1);
+    }
+    nodes
+}
+
+// perft split by root move
+pub fn divide(board: &Board, depth: u8) -> Vec<(ChessMove, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    MoveGen::new_legal(board)
+        .map(|m| (m, perft(&board.make_move_new(m), depth - 1)))
+        .collect()
+}
+
+pub fn print_perft(board: &Board, depth: u8) {
+    let start = Instant::now();
+    let nodes = perft(board, depth);
+    print_summary(nodes, start);
+}
+
+pub fn print_divide(board: &Board, depth: u8) {
+    let start = Instant::now();
+    let mut nodes = 0;
+    for (m, count) in divide(board, depth) {
+        println!("{}: {}", m, count);
+        nodes += count;
+    }
+    println!();
+    print_summary(nodes, start);
+}
+
+fn print_summary(nodes: u64, start: Instant) {
+    let elapsed = start.elapsed();
+    let nps = (nodes as f64 / elapsed.as_secs_f64().max(1e-9)) as u64;
+    println!("Nodes searched: {}", nodes);
+    println!("Time: {} ms, nps: {}", elapsed.as_millis(), nps);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // https://www.chessprogramming.org/Perft_Results
+    fn check(fen: &str, expected: &[u64]) {
+        let board = Board::from_str(fen).unwrap();
+        for (depth, nodes) in expected.iter().enumerate() {
+            assert_eq!(
+                perft(&board, depth as u8 + 1),
+                *nodes,
+                "{} depth {}",
+                fen,
+                depth + 1
+            );
+        }
+    }
+
+    #[test]
+    fn startpos() {
+        check(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[20, 400, 8902, 197281, 4865609],
+        );
+    }
+
+    #[test]
+    fn kiwipete() {
+        check(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            &[48, 2039, 97862, 4085603],
+        );
+    }
+
+    #[test]
+    fn position_3() {
+        check(
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            &[14, 191, 2812, 43238, 674624],
+        );
+    }
+
+    #[test]
+    fn position_4() {
+        check(
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            &[6, 264, 9467, 422333],
+        );
+    }
+
+    #[test]
+    fn position_4_mirrored() {
+        check(
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+            &[6, 264, 9467, 422333],
+        );
+    }
+
+    #[test]
+    fn position_5() {
+        check(
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            &[44, 1486, 62379, 2103487],
+        );
+    }
+
+    #[test]
+    fn position_6() {
+        check(
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            &[46, 2079, 89890, 3894594],
+        );
+    }
+
+    #[test]
+    fn divide_sums_to_perft() {
+        let board = Board::default();
+        let total: u64 = divide(&board, 3).iter().map(|(_, n)| n).sum();
+        assert_eq!(total, perft(&board, 3));
+    }
+}
@@ -0,0 +1,748 @@
+use crate::mate::MAX_MATE_MOVES;
+use crate::position::Position;
+use crate::san::{parse_san, to_san};
+use crate::score::Score;
+use chess::{ChessMove, Color};
+
+// Portable Game Notation. Reading is forgiving about what online databases produce: a byte order
+// mark, CRLF line ends, % escape lines, move numbers glued to moves, !? suffixes, missing tags or
+// results, and comments left open, which end with their line. A game with an illegal move in its
+// main line is reported as an error without stopping the games after it; an illegal move inside
+// a variation only drops the rest of that variation.
+
+const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*"),
+];
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+// movetext is wrapped below this many characters per line
+const LINE_WIDTH: usize = 80;
+
+/// One position of a game tree and the move that led to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameNode {
+    /// The position after the move.
+    pub position: Position,
+    /// `None` for the start position at the root.
+    pub m: Option<ChessMove>,
+    pub parent: Option<usize>,
+    /// Moves from this position; the first continues the line, the others are variations.
+    pub children: Vec<usize>,
+    /// The comment after the move, or before the first move at the root.
+    pub comment: Option<String>,
+    /// A comment before the move, as at the start of a variation.
+    pub comment_before: Option<String>,
+    /// Numeric annotation glyphs, `$1` or `!` is 1.
+    pub nags: Vec<u8>,
+    /// An engine evaluation from white's point of view, read and written as `[%eval ...]`.
+    pub eval: Option<Score>,
+}
+
+/// A game with its tags and a tree of moves and variations. `nodes[0]` is the start position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Game {
+    /// Tag pairs in the order they were read or set.
+    pub tags: Vec<(String, String)>,
+    pub nodes: Vec<GameNode>,
+    /// `1-0`, `0-1`, `1/2-1/2` or `*`.
+    pub result: String,
+}
+
+impl Game {
+    /// A game with no moves and no tags.
+    pub fn new(start: Position) -> Game {
+        Game {
+            tags: Vec::new(),
+            nodes: vec![GameNode {
+                position: start,
+                m: None,
+                parent: None,
+                children: Vec::new(),
+                comment: None,
+                comment_before: None,
+                nags: Vec::new(),
+                eval: None,
+            }],
+            result: String::from("*"),
+        }
+    }
+
+    pub fn start(&self) -> &Position {
+        &self.nodes[0].position
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(n, _)| n == name) {
+            Some(tag) => tag.1 = String::from(value),
+            None => self.tags.push((String::from(name), String::from(value))),
+        }
+    }
+
+    /// Plays `m` from node `parent` and returns the node it leads to, reusing the child if the
+    /// move is already there. A new move becomes a variation if `parent` already has a child.
+    pub fn add_move(&mut self, parent: usize, m: ChessMove) -> Result<usize, String> {
+        let existing = self.nodes[parent]
+            .children
+            .iter()
+            .find(|&&child| self.nodes[child].m == Some(m));
+        if let Some(&child) = existing {
+            return Ok(child);
+        }
+        if !self.nodes[parent].position.board.legal(m) {
+            return Err(format!("illegal move {}", m));
+        }
+        Ok(self.push(parent, m))
+    }
+
+    /// The nodes of the main line after the start position.
+    pub fn mainline(&self) -> Vec<usize> {
+        let mut line = Vec::new();
+        let mut node = 0;
+        while let Some(&next) = self.nodes[node].children.first() {
+            line.push(next);
+            node = next;
+        }
+        line
+    }
+
+    // adds a child without checking the move
+    fn push(&mut self, parent: usize, m: ChessMove) -> usize {
+        let position = self.nodes[parent].position.make_move_new(m);
+        self.nodes.push(GameNode {
+            position,
+            m: Some(m),
+            parent: Some(parent),
+            children: Vec::new(),
+            comment: None,
+            comment_before: None,
+            nags: Vec::new(),
+            eval: None,
+        });
+        let node = self.nodes.len() - 1;
+        self.nodes[parent].children.push(node);
+        node
+    }
+}
+
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Open,
+    Close,
+    Nag(u8),
+    Word(String),
+}
+
+fn tokens(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    let mut line_start = true;
+    while let Some(c) = chars.next() {
+        let at_line_start = line_start;
+        line_start = c == '\n';
+        match c {
+            // an escape line, for data meant for other programs
+            '%' if at_line_start => {
+                chars.by_ref().take_while(|&c| c != '\n').for_each(drop);
+                line_start = true;
+            }
+            c if c.is_whitespace() => {}
+            '[' => tokens.push(tag(&mut chars)),
+            '{' => {
+                // a comment that is never closed ends with its line
+                let end = if chars.clone().any(|c| c == '}') {
+                    '}'
+                } else {
+                    '\n'
+                };
+                let comment: String = chars.by_ref().take_while(|&c| c != end).collect();
+                tokens.push(Token::Comment(comment));
+                line_start = end == '\n';
+            }
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|&c| c != '\n').collect();
+                tokens.push(Token::Comment(comment));
+                line_start = true;
+            }
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '$' => {
+                let mut digits = String::new();
+                while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+                    digits.push(d);
+                }
+                if let Ok(nag) = digits.parse() {
+                    tokens.push(Token::Nag(nag));
+                }
+            }
+            c => {
+                let mut word = String::from(c);
+                while let Some(c) =
+                    chars.next_if(|c| !c.is_whitespace() && !"{}()[];$".contains(*c))
+                {
+                    word.push(c);
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    tokens
+}
+
+// the rest of `[Name "value"]`, with \" and \\ escapes in the value
+fn tag(chars: &mut std::iter::Peekable<std::str::Chars>) -> Token {
+    let inside: String = chars.by_ref().take_while(|&c| c != ']').collect();
+    let inside = inside.trim();
+    let (name, rest) = inside
+        .split_once(char::is_whitespace)
+        .unwrap_or((inside, ""));
+    let rest = rest.trim();
+    let value = match rest.strip_prefix('"') {
+        Some(quoted) => {
+            let mut value = String::new();
+            let mut escaped = false;
+            for c in quoted.chars() {
+                match c {
+                    '\\' if !escaped => escaped = true,
+                    '"' if !escaped => break,
+                    c => {
+                        value.push(c);
+                        escaped = false;
+                    }
+                }
+            }
+            value
+        }
+        None => String::from(rest),
+    };
+    Token::Tag(String::from(name.trim()), value)
+}
+
+// the numeric glyph of a move suffix such as !?
+fn suffix_nag(suffix: &str) -> Option<u8> {
+    match suffix {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    }
+}
+
+// collapses the whitespace of a comment, None if nothing is left
+fn clean(comment: &str) -> Option<String> {
+    let words: Vec<&str> = comment.split_whitespace().collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+fn append(comment: &mut Option<String>, text: &str) {
+    *comment = match (comment.take(), clean(text)) {
+        (Some(old), Some(new)) => Some(format!("{} {}", old, new)),
+        (old, new) => old.or(new),
+    };
+}
+
+// takes a [%eval ...] command out of a comment; `position` is the one the eval is for
+fn take_eval(comment: &str, position: &Position) -> (Option<Score>, String) {
+    let Some(start) = comment.find("[%eval") else {
+        return (None, String::from(comment));
+    };
+    let Some(length) = comment[start..].find(']') else {
+        return (None, String::from(comment));
+    };
+    let command = &comment[start + "[%eval".len()..start + length];
+    // the eval may be followed by a depth, as in [%eval 0.25,18]
+    let value = command.trim().split(',').next().unwrap_or("");
+    let rest = format!("{} {}", &comment[..start], &comment[start + length + 1..]);
+    (parse_eval(value, position), rest)
+}
+
+// "0.35", "-1.2", "#3" or "#-2", always from white's point of view
+fn parse_eval(value: &str, position: &Position) -> Option<Score> {
+    let to_move = position.board.side_to_move();
+    match value.strip_prefix('#') {
+        Some(moves) => {
+            let moves: i32 = moves.parse().ok()?;
+            // longer mates have no score, and would overflow the ply count
+            if moves.unsigned_abs() > u32::from(MAX_MATE_MOVES) {
+                return None;
+            }
+            // the side to move needs one ply less to mate than the other side does
+            let plies = |mater: Color| moves.abs() * 2 - i32::from(mater == to_move);
+            Some(match moves {
+                0 => Score::mated_in_plies(0).for_side(to_move),
+                m if m > 0 => Score::mate_in_plies(plies(Color::White)),
+                _ => Score::mated_in_plies(plies(Color::Black)),
+            })
+        }
+        None => {
+            let pawns: f64 = value.parse().ok()?;
+            Some(Score::cp((pawns * 100.0).round() as i32))
+        }
+    }
+}
+
+fn eval_text(score: Score) -> String {
+    match score.mate_in() {
+        Some(moves) => format!("#{}", moves),
+        None => format!("{:.2}", score.centipawns() as f64 / 100.0),
+    }
+}
+
+// builds one game from its tokens
+struct Reader {
+    game: Game,
+    // the node the next move is played from
+    current: usize,
+    // where to return to at the end of each open variation
+    stack: Vec<usize>,
+    // no move yet in the current variation, so a comment belongs to the next move
+    line_start: bool,
+    before: Option<String>,
+    // open variations left to skip after an illegal move in one
+    skip: usize,
+    error: Option<String>,
+    movetext: bool,
+    result: Option<String>,
+}
+
+impl Reader {
+    fn new() -> Reader {
+        Reader {
+            game: Game::new(Position::default()),
+            current: 0,
+            stack: Vec::new(),
+            line_start: true,
+            before: None,
+            skip: 0,
+            error: None,
+            movetext: false,
+            result: None,
+        }
+    }
+
+    fn tag(&mut self, name: String, value: String) {
+        if name == "FEN" {
+            match Position::from_fen(&value) {
+                Ok(position) => self.game.nodes[0].position = position,
+                Err(error) => self.error = Some(format!("invalid FEN tag: {}", error)),
+            }
+        }
+        self.game.tags.push((name, value));
+    }
+
+    fn token(&mut self, token: Token) {
+        self.movetext = true;
+        if self.error.is_some() {
+            return;
+        }
+        if self.skip > 0 {
+            match token {
+                Token::Open => self.skip += 1,
+                Token::Close => {
+                    self.skip -= 1;
+                    if self.skip == 0 {
+                        self.close();
+                    }
+                }
+                _ => {}
+            }
+            return;
+        }
+        match token {
+            Token::Open => {
+                self.stack.push(self.current);
+                // a variation replaces the last move
+                self.current = self.game.nodes[self.current].parent.unwrap_or(self.current);
+                self.line_start = true;
+                self.before = None;
+            }
+            Token::Close => self.close(),
+            Token::Nag(nag) if !self.line_start => self.game.nodes[self.current].nags.push(nag),
+            Token::Nag(_) => {}
+            Token::Comment(text) if self.line_start && !self.stack.is_empty() => {
+                append(&mut self.before, &text)
+            }
+            Token::Comment(text) => {
+                let node = &mut self.game.nodes[self.current];
+                let (eval, text) = take_eval(&text, &node.position);
+                node.eval = eval.or(node.eval);
+                append(&mut node.comment, &text);
+            }
+            Token::Word(word) => self.word(&word),
+            Token::Tag(..) => {}
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some(node) = self.stack.pop() {
+            self.current = node;
+            self.line_start = false;
+            self.before = None;
+        }
+    }
+
+    fn word(&mut self, word: &str) {
+        // move numbers, alone or glued to the move as in 12.e4 or 12...e5; 0-0 is castling
+        let digits = word.trim_start_matches(|c: char| c.is_ascii_digit());
+        let word = if digits.is_empty() || digits.starts_with('.') {
+            digits.trim_start_matches('.')
+        } else {
+            word
+        };
+        if word.is_empty() {
+            return;
+        }
+        let san = word.trim_end_matches(['!', '?']);
+        let board = &self.game.nodes[self.current].position.board;
+        match parse_san(board, san) {
+            Ok(m) => {
+                let node = self.game.push(self.current, m);
+                self.game.nodes[node].comment_before = self.before.take();
+                self.game.nodes[node]
+                    .nags
+                    .extend(suffix_nag(&word[san.len()..]));
+                self.current = node;
+                self.line_start = false;
+            }
+            Err(error) if self.stack.is_empty() => self.error = Some(error),
+            // the rest of this variation cannot be read
+            Err(_) => self.skip = 1,
+        }
+    }
+
+    fn finish(mut self) -> Result<Game, String> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let result = self
+            .result
+            .or_else(|| self.game.tag("Result").map(String::from));
+        self.game.result = result
+            .filter(|r| RESULTS.contains(&r.as_str()))
+            .unwrap_or_else(|| String::from("*"));
+        Ok(self.game)
+    }
+}
+
+/// Reads every game in `text`. Each game is read on its own, so one broken game only turns its
+/// own entry into an error.
+pub fn read_pgn(text: &str) -> Vec<Result<Game, String>> {
+    let mut games = Vec::new();
+    let mut reader = Reader::new();
+    for token in tokens(text) {
+        match token {
+            Token::Tag(name, value) => {
+                // tags after movetext start the next game, even without a result between
+                if reader.movetext {
+                    games.push(std::mem::replace(&mut reader, Reader::new()).finish());
+                }
+                reader.tag(name, value);
+            }
+            Token::Word(word) if RESULTS.contains(&word.as_str()) || word == "½-½" => {
+                // results inside variations are noise
+                if reader.stack.is_empty() || reader.error.is_some() {
+                    let word = if word == "½-½" { "1/2-1/2" } else { &word };
+                    reader.result = Some(String::from(word));
+                    games.push(std::mem::replace(&mut reader, Reader::new()).finish());
+                }
+            }
+            token => reader.token(token),
+        }
+    }
+    if reader.movetext || !reader.game.tags.is_empty() {
+        games.push(reader.finish());
+    }
+    games
+}
+
+// the move of `node` with its number, glyphs and comments
+fn write_move(game: &Game, node: usize, numbered: bool, words: &mut Vec<String>) {
+    let node = &game.nodes[node];
+    let before = &game.nodes[node.parent.unwrap_or(0)].position;
+    if let Some(comment) = &node.comment_before {
+        words.push(format!("{{{}}}", comment));
+    }
+    let number = before.fullmove_number;
+    match before.board.side_to_move() {
+        Color::White => words.push(format!("{}.", number)),
+        Color::Black if numbered || node.comment_before.is_some() => {
+            words.push(format!("{}...", number))
+        }
+        Color::Black => {}
+    }
+    if let Some(m) = node.m {
+        words.push(to_san(&before.board, m));
+    }
+    words.extend(node.nags.iter().map(|nag| format!("${}", nag)));
+    let eval = node
+        .eval
+        .map(|score| format!("[%eval {}]", eval_text(score)));
+    let comment: Vec<&str> = eval
+        .iter()
+        .chain(&node.comment)
+        .map(String::as_str)
+        .collect();
+    if !comment.is_empty() {
+        words.push(format!("{{{}}}", comment.join(" ")));
+    }
+}
+
+// the line continuing from `node`, with the alternatives to each move in parentheses
+fn write_line(game: &Game, mut node: usize, words: &mut Vec<String>) {
+    let mut numbered = true;
+    while let Some((&main, variations)) = game.nodes[node].children.split_first() {
+        write_move(game, main, numbered, words);
+        numbered = game.nodes[main].comment.is_some() || game.nodes[main].eval.is_some();
+        for &variation in variations {
+            words.push(String::from("("));
+            write_move(game, variation, true, words);
+            write_line(game, variation, words);
+            words.push(String::from(")"));
+            numbered = true;
+        }
+        node = main;
+    }
+}
+
+/// The game as PGN: the seven tag roster, the other tags, then the movetext with variations,
+/// comments, `$` glyphs and evaluations as `{[%eval ...]}`.
+pub fn write_pgn(game: &Game) -> String {
+    let mut tags: Vec<(String, String)> = SEVEN_TAG_ROSTER
+        .iter()
+        .map(|&(name, default)| {
+            let value = match name {
+                "Result" => &game.result,
+                _ => game.tag(name).unwrap_or(default),
+            };
+            (String::from(name), String::from(value))
+        })
+        .collect();
+    let roster = |name: &str| SEVEN_TAG_ROSTER.iter().any(|&(n, _)| n == name);
+    tags.extend(game.tags.iter().filter(|(n, _)| !roster(n)).cloned());
+    if *game.start() != Position::default() && game.tag("FEN").is_none() {
+        tags.push((String::from("SetUp"), String::from("1")));
+        tags.push((String::from("FEN"), game.start().to_fen()));
+    }
+    let mut text = String::new();
+    for (name, value) in tags {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        text.push_str(&format!("[{} \"{}\"]\n", name, value));
+    }
+    text.push('\n');
+
+    let mut words = Vec::new();
+    if let Some(comment) = &game.nodes[0].comment {
+        words.push(format!("{{{}}}", comment));
+    }
+    write_line(game, 0, &mut words);
+    words.push(game.result.clone());
+    // parentheses go against the moves they enclose
+    let mut joined: Vec<String> = Vec::new();
+    let mut open = false;
+    for word in &words {
+        match (word.as_str(), joined.last_mut()) {
+            ("(", _) => open = true,
+            (")", Some(last)) => last.push(')'),
+            (word, _) if open => {
+                joined.push(format!("({}", word));
+                open = false;
+            }
+            (word, _) => joined.push(String::from(word)),
+        }
+    }
+    // long comments may be broken between their words, but not inside an [%eval] command
+    let mut pieces: Vec<String> = Vec::new();
+    for piece in joined.iter().flat_map(|w| w.split(' ')) {
+        match pieces.last_mut() {
+            Some(last) if last.ends_with("[%eval") => last.push_str(&format!(" {}", piece)),
+            _ => pieces.push(String::from(piece)),
+        }
+    }
+    let mut line = String::new();
+    for word in &pieces {
+        if !line.is_empty() && line.len() + 1 + word.len() >= LINE_WIDTH {
+            text.push_str(&line);
+            text.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    text.push_str(&line);
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn moves(game: &Game, line: &[usize]) -> Vec<String> {
+        line.iter()
+            .map(|&n| {
+                let node = &game.nodes[n];
+                let parent = &game.nodes[node.parent.unwrap()];
+                to_san(&parent.position.board, node.m.unwrap())
+            })
+            .collect()
+    }
+
+    const GAME: &str = r#"[Event "Casual \"blitz\""]
+[Site "?"]
+[White "A"]
+[Black "B"]
+[Result "1-0"]
+
+{A short one.} 1. e4 e5 2. Nf3 (2. Bc4 {the bishop} Nf6 (2... Bc5 3. Qh5) 3. d3)
+2... Nc6 $1 3. Bb5 {[%eval 0.35] Spanish} a6?! 1-0
+"#;
+
+    #[test]
+    fn reads_a_game_tree() {
+        let games = read_pgn(GAME);
+        assert_eq!(games.len(), 1);
+        let game = games[0].as_ref().unwrap();
+        assert_eq!(game.tag("Event"), Some("Casual \"blitz\""));
+        assert_eq!(game.result, "1-0");
+        assert_eq!(game.nodes[0].comment.as_deref(), Some("A short one."));
+        let main = game.mainline();
+        assert_eq!(moves(game, &main), ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6"]);
+        assert_eq!(game.nodes[main[3]].nags, [1]);
+        assert_eq!(game.nodes[main[5]].nags, [6]);
+        let bb5 = &game.nodes[main[4]];
+        assert_eq!(bb5.comment.as_deref(), Some("Spanish"));
+        assert_eq!(bb5.eval, Some(Score::cp(35)));
+        // 2. Bc4 is a variation on 2. Nf3, with 2... Bc5 nested in it
+        let e5 = &game.nodes[main[1]];
+        assert_eq!(e5.children.len(), 2);
+        let bc4 = e5.children[1];
+        assert_eq!(game.nodes[bc4].comment.as_deref(), Some("the bishop"));
+        let nf6 = game.nodes[bc4].children[0];
+        assert_eq!(game.nodes[bc4].children.len(), 2);
+        assert_eq!(moves(game, &game.nodes[nf6].children), ["d3"]);
+    }
+
+    #[test]
+    fn writes_what_it_reads() {
+        let game = read_pgn(GAME).remove(0).unwrap();
+        let text = write_pgn(&game);
+        assert!(text.starts_with("[Event \"Casual \\\"blitz\\\"\"]\n[Site \"?\"]\n"));
+        assert!(text.contains("[Date \"????.??.??\"]\n[Round \"?\"]\n[White \"A\"]"));
+        assert!(text.contains(
+            "{A short one.} 1. e4 e5 2. Nf3 (2. Bc4 {the bishop} 2... Nf6 (2... Bc5 3. Qh5)"
+        ));
+        assert!(text.contains("3. d3) 2... Nc6 $1 3. Bb5 {[%eval 0.35] Spanish} 3... a6 $6 1-0"));
+        let reread = read_pgn(&text).remove(0).unwrap();
+        assert_eq!(reread.nodes, game.nodes);
+        assert_eq!(reread.result, game.result);
+        assert!(text.lines().all(|line| line.len() < LINE_WIDTH));
+    }
+
+    #[test]
+    fn tolerates_messy_input() {
+        let text =
+            "\u{feff}[Event \"one\"]\r\n% an escape line\r\n1.e4 e5 2.Nf3!! ; rest of line\r\n\
+                    Nc6 3.Bc4 Nf6 4.0-0?? {unterminated\r\n\r\n[Event \"two\"]\n1. d4 ½-½\n\n\
+                    [Event \"three\"]\n1. e4 e5 2. Ke3 Nc6 *\n\
+                    [Event \"four\"]\n1. e4 (1. e5 d5) (1. d4 d5) e5 *";
+        let games = read_pgn(text);
+        assert_eq!(games.len(), 4);
+        // no result, and a comment running to the end of the game
+        let one = games[0].as_ref().unwrap();
+        assert_eq!(one.result, "*");
+        assert_eq!(
+            moves(one, &one.mainline()),
+            ["e4", "e5", "Nf3", "Nc6", "Bc4", "Nf6", "O-O"]
+        );
+        assert_eq!(one.nodes[one.mainline()[6]].nags, [4]);
+        assert_eq!(one.nodes[one.mainline()[2]].nags, [3]);
+        let two = games[1].as_ref().unwrap();
+        assert_eq!(two.result, "1/2-1/2");
+        // an illegal move spoils its game only
+        assert_eq!(games[2], Err(String::from("illegal move 'Ke3'")));
+        // and in a variation it only cuts the variation short
+        let four = games[3].as_ref().unwrap();
+        assert_eq!(moves(four, &four.mainline()), ["e4", "e5"]);
+        assert_eq!(moves(four, &four.nodes[0].children), ["e4", "d4"]);
+    }
+
+    #[test]
+    fn starts_from_a_fen() {
+        let fen = "4k3/8/8/8/8/8/8/4K2R w K - 0 1";
+        let game = read_pgn(&format!(
+            "[SetUp \"1\"]\n[FEN \"{}\"]\n\n1. O-O+ Kd7 *",
+            fen
+        ))
+        .remove(0)
+        .unwrap();
+        assert_eq!(game.start().to_fen(), fen);
+        let mut fresh = Game::new(*game.start());
+        let castle = fresh
+            .add_move(0, ChessMove::from_str("e1g1").unwrap())
+            .unwrap();
+        assert!(write_pgn(&fresh).contains(&format!("[FEN \"{}\"]", fen)));
+        assert_eq!(
+            fresh.add_move(0, ChessMove::from_str("e1g1").unwrap()),
+            Ok(castle)
+        );
+        assert!(fresh
+            .add_move(castle, ChessMove::from_str("e1g1").unwrap())
+            .is_err());
+        assert!(write_pgn(&fresh).ends_with("1. O-O *\n"));
+        assert!(read_pgn("[FEN \"nonsense\"]\n1. e4 *")[0].is_err());
+    }
+
+    #[test]
+    fn evals_round_trip() {
+        let text = "1. e4 {[%eval 0.2]} e5 {[%eval -0.15,20] solid} \
+                    2. Qh5 {[%eval #-2]} Nc6 {[%eval #2]} 3. Bc4 Nf6 4. Qxf7# {[%eval #0]} 1-0";
+        let game = read_pgn(text).remove(0).unwrap();
+        let main = game.mainline();
+        let evals: Vec<Option<Score>> = main.iter().map(|&n| game.nodes[n].eval).collect();
+        assert_eq!(evals[0], Some(Score::cp(20)));
+        assert_eq!(evals[1], Some(Score::cp(-15)));
+        assert_eq!(game.nodes[main[1]].comment.as_deref(), Some("solid"));
+        // black mates in 2 after Qh5, white in 2 after Nc6; mated with black to move is #0
+        assert_eq!(evals[2].and_then(Score::mate_in), Some(-2));
+        assert_eq!(evals[3].and_then(Score::mate_in), Some(2));
+        assert_eq!(evals[6].and_then(Score::mate_plies), Some(0));
+        let written = write_pgn(&game);
+        assert!(written.contains("{[%eval 0.20]}"));
+        assert!(written.contains("{[%eval -0.15] solid}"));
+        assert!(written.contains("{[%eval #-2]}"));
+        assert_eq!(read_pgn(&written).remove(0).unwrap().nodes, game.nodes);
+    }
+
+    #[test]
+    fn drops_mates_no_score_can_hold() {
+        let eval = |value: &str| {
+            let text = format!("1. e4 {{[%eval {}]}} *", value);
+            read_pgn(&text).remove(0).unwrap().nodes[1].eval
+        };
+        assert_eq!(eval("#128").and_then(Score::mate_in), Some(128));
+        assert_eq!(eval("#-128").and_then(Score::mate_in), Some(-128));
+        for value in ["#129", "#99999", "#-99999", "#1073741824", "#-2147483648"] {
+            assert_eq!(eval(value), None, "{}", value);
+        }
+    }
+}
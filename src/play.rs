@@ -0,0 +1,343 @@
+use rusty::chess::{Board, ChessMove, Color, File, Piece, Rank, Square};
+use rusty::{outcome, parse_san, to_san, write_pgn, Outcome};
+use rusty::{Engine, EngineConfig, Position, Score, SearchLimits};
+use std::io::Write;
+use std::time::Duration;
+
+// a game against the engine in the terminal
+
+const HELP: &str = "\
+moves      e2e4, e7e8q or SAN such as Nf3, exd5, O-O, e8=Q
+undo       take back your last move and the engine's reply
+flip       turn the board around
+fen        print the position as FEN
+hint       suggest a move
+depth <n>  let the engine search n plies per move
+time <s>   let the engine think s seconds per move
+go         let the engine play the side to move
+new        start a new game
+save [f]   write the game as PGN to file f, or show it
+help       show this list
+quit       leave";
+
+struct Game {
+    engine: Engine,
+    // every position of the game so far, the current one last
+    history: Vec<Position>,
+    // the move into each later position, with the engine's evaluation of its own moves
+    moves: Vec<(ChessMove, Option<Score>)>,
+    human: Color,
+    flipped: bool,
+    unicode: bool,
+    limits: SearchLimits,
+}
+
+impl Game {
+    fn position(&self) -> &Position {
+        self.history.last().unwrap()
+    }
+
+    fn board(&self) -> &Board {
+        &self.position().board
+    }
+
+    fn show(&self) {
+        println!();
+        for line in render(self.board(), self.flipped, self.unicode) {
+            println!("{}", line);
+        }
+        println!();
+    }
+
+    // false once the player has asked to quit
+    fn handle(&mut self, line: &str) -> bool {
+        let mut args = line.split_whitespace();
+        let command = args.next().unwrap_or("");
+        match command {
+            "" => {}
+            "quit" | "exit" => return false,
+            "help" => println!("{}", HELP),
+            "flip" => {
+                self.flipped = !self.flipped;
+                self.show();
+            }
+            "fen" => println!("{}", self.position().to_fen()),
+            "hint" => match self.search().best_move {
+                Some(m) => println!("hint: {}", to_san(self.board(), m)),
+                None => println!("the game is over"),
+            },
+            "undo" => self.undo(),
+            "depth" => match args.next().map(|d| d.parse::<u8>()) {
+                Some(Ok(depth)) if depth > 0 => {
+                    self.limits = SearchLimits {
+                        depth: Some(depth),
+                        ..Default::default()
+                    }
+                }
+                _ => println!("usage: depth <plies>"),
+            },
+            "time" => match args.next().map(|t| t.parse::<f64>()) {
+                Some(Ok(seconds)) if seconds > 0.0 => {
+                    self.limits = SearchLimits {
+                        movetime: Some(Duration::from_secs_f64(seconds)),
+                        ..Default::default()
+                    }
+                }
+                _ => println!("usage: time <seconds>"),
+            },
+            "go" => {
+                self.human = !self.board().side_to_move();
+                self.reply();
+            }
+            "save" => match args.next() {
+                Some(path) => match std::fs::write(path, write_pgn(&self.record())) {
+                    Ok(()) => println!("saved to {}", path),
+                    Err(error) => println!("cannot write {}: {}", path, error),
+                },
+                None => print!("{}", write_pgn(&self.record())),
+            },
+            "new" => {
+                self.history.truncate(1);
+                self.moves.clear();
+                self.show();
+                self.reply();
+            }
+            _ => match parse_san(self.board(), command) {
+                Ok(_) if outcome(&self.history).is_some() => println!("the game is over"),
+                Ok(m) => {
+                    self.play(m, None);
+                    self.reply();
+                }
+                Err(error) => println!("{} (try help)", error),
+            },
+        }
+        true
+    }
+
+    fn search(&mut self) -> rusty::SearchResult {
+        self.engine.set_position(*self.position());
+        self.engine.search(self.limits)
+    }
+
+    // `eval` is from white's point of view
+    fn play(&mut self, m: ChessMove, eval: Option<Score>) {
+        let next = self.position().make_move_new(m);
+        self.history.push(next);
+        self.moves.push((m, eval));
+    }
+
+    // the game so far as PGN
+    fn record(&self) -> rusty::Game {
+        let mut record = rusty::Game::new(self.history[0]);
+        let (white, black) = match self.human {
+            Color::White => ("Player", "Rusty"),
+            Color::Black => ("Rusty", "Player"),
+        };
+        record.set_tag("Event", "Casual game");
+        record.set_tag("White", white);
+        record.set_tag("Black", black);
+        let mut node = 0;
+        for &(m, eval) in &self.moves {
+            let Ok(next) = record.add_move(node, m) else {
+                break;
+            };
+            record.nodes[next].eval = eval;
+            node = next;
+        }
+        record.result = String::from(outcome(&self.history).map_or("*", Outcome::result));
+        record
+    }
+
+    // the engine moves if it is its turn, then the game is shown
+    fn reply(&mut self) {
+        if outcome(&self.history).is_none() && self.board().side_to_move() != self.human {
+            let result = self.search();
+            if let Some(m) = result.best_move {
+                println!(
+                    "Rusty plays {} ({}, depth {})",
+                    to_san(self.board(), m),
+                    result.score,
+                    result.depth
+                );
+                let eval = result.score.for_side(self.board().side_to_move());
+                self.play(m, Some(eval));
+            }
+        }
+        self.show();
+        if let Some(outcome) = outcome(&self.history) {
+            println!("{}, {}", outcome.result(), outcome.reason().to_lowercase());
+        }
+    }
+
+    fn undo(&mut self) {
+        if self.history.len() == 1 {
+            println!("nothing to undo");
+            return;
+        }
+        self.history.pop();
+        self.moves.pop();
+        // back to a position where the player is to move
+        if self.board().side_to_move() != self.human && self.history.len() > 1 {
+            self.history.pop();
+            self.moves.pop();
+        }
+        self.show();
+    }
+}
+
+// the board as text, white at the bottom unless flipped
+fn render(board: &Board, flipped: bool, unicode: bool) -> Vec<String> {
+    let ranks: Vec<usize> = if flipped {
+        (0..8).collect()
+    } else {
+        (0..8).rev().collect()
+    };
+    let files: Vec<usize> = if flipped {
+        (0..8).rev().collect()
+    } else {
+        (0..8).collect()
+    };
+    let mut lines = Vec::new();
+    for &rank in &ranks {
+        let mut line = format!("{} ", rank + 1);
+        for &file in &files {
+            let square = Square::make_square(Rank::from_index(rank), File::from_index(file));
+            line.push(' ');
+            line.push(match (board.piece_on(square), board.color_on(square)) {
+                (Some(piece), Some(color)) => piece_char(piece, color, unicode),
+                _ if unicode => '·',
+                _ => '.',
+            });
+        }
+        lines.push(line);
+    }
+    let letters: Vec<String> = files
+        .iter()
+        .map(|&f| ((b'a' + f as u8) as char).to_string())
+        .collect();
+    lines.push(format!("   {}", letters.join(" ")));
+    lines
+}
+
+fn piece_char(piece: Piece, color: Color, unicode: bool) -> char {
+    let index = piece.to_index();
+    match (unicode, color) {
+        (false, Color::White) => ['P', 'N', 'B', 'R', 'Q', 'K'][index],
+        (false, Color::Black) => ['p', 'n', 'b', 'r', 'q', 'k'][index],
+        (true, Color::White) => ['♙', '♘', '♗', '♖', '♕', '♔'][index],
+        (true, Color::Black) => ['♟', '♞', '♝', '♜', '♛', '♚'][index],
+    }
+}
+
+// play [--black] [--depth n | --movetime ms] [--unicode] [--fen <fen>]
+pub(crate) fn main(args: &[String], config: EngineConfig) -> Result<(), String> {
+    let mut game = Game {
+        engine: Engine::new(config),
+        history: vec![Position::default()],
+        moves: Vec::new(),
+        human: Color::White,
+        flipped: false,
+        unicode: false,
+        limits: SearchLimits {
+            movetime: Some(Duration::from_secs(1)),
+            ..Default::default()
+        },
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--black" => {
+                game.human = Color::Black;
+                game.flipped = true;
+            }
+            "--unicode" => game.unicode = true,
+            "--depth" => {
+                let depth = value()?.parse().map_err(|_| "invalid depth")?;
+                game.limits = SearchLimits {
+                    depth: Some(depth),
+                    ..Default::default()
+                };
+            }
+            "--movetime" => {
+                let millis = value()?.parse().map_err(|_| "invalid movetime")?;
+                game.limits = SearchLimits {
+                    movetime: Some(Duration::from_millis(millis)),
+                    ..Default::default()
+                };
+            }
+            "--fen" => {
+                let position =
+                    Position::from_fen(value()?).map_err(|e| format!("invalid fen: {}", e))?;
+                game.history = vec![position];
+            }
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    println!("type a move, or help for the commands");
+    game.reply();
+    let mut line = String::new();
+    loop {
+        print!("> ");
+        std::io::stdout().flush().ok();
+        line.clear();
+        if !matches!(std::io::stdin().read_line(&mut line), Ok(n) if n > 0) {
+            return Ok(());
+        }
+        if !game.handle(&line) {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_both_ways() {
+        let lines = render(&Board::default(), false, false);
+        assert_eq!(lines[0], "8  r n b q k b n r");
+        assert_eq!(lines[7], "1  R N B Q K B N R");
+        assert_eq!(lines[8], "   a b c d e f g h");
+        let flipped = render(&Board::default(), true, true);
+        assert_eq!(flipped[0], "1  ♖ ♘ ♗ ♔ ♕ ♗ ♘ ♖");
+        assert_eq!(flipped[3], "4  · · · · · · · ·");
+        assert_eq!(flipped[8], "   h g f e d c b a");
+    }
+
+    #[test]
+    fn commands_and_undo() {
+        let mut game = Game {
+            engine: Engine::new(EngineConfig::default()),
+            history: vec![Position::default()],
+            moves: Vec::new(),
+            human: Color::White,
+            flipped: false,
+            unicode: false,
+            limits: SearchLimits {
+                depth: Some(1),
+                ..Default::default()
+            },
+        };
+        game.handle("e4");
+        // the engine has answered
+        assert_eq!(game.history.len(), 3);
+        game.handle("nonsense");
+        assert_eq!(game.history.len(), 3);
+        // the record has the engine's evaluation on its move only
+        let record = game.record();
+        let main = record.mainline();
+        assert_eq!(main.len(), 2);
+        assert_eq!(record.nodes[main[0]].eval, None);
+        assert!(record.nodes[main[1]].eval.is_some());
+        assert_eq!(record.tag("White"), Some("Player"));
+        assert!(write_pgn(&record).contains("1. e4 "));
+        game.handle("undo");
+        assert_eq!(game.history.len(), 1);
+        assert!(game.moves.is_empty());
+        game.handle("flip");
+        assert!(game.flipped);
+        assert!(!game.handle("quit"));
+    }
+}
@@ -0,0 +1,221 @@
+use chess::{Board, BoardStatus, ChessMove, Color, Piece};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::fen::{parse_fen, START_FEN};
+
+// Board plus the two move counters that the chess crate drops
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub board: Board,
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+}
+
+impl Position {
+    pub fn from_fen(fen: &str) -> Result<Position, String> {
+        let board = parse_fen(fen)?;
+        // parse_fen has already validated the counters if they are present
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let halfmove_clock = fields.get(4).map_or(0, |f| f.parse().unwrap_or(0));
+        let fullmove_number = fields.get(5).map_or(1, |f| f.parse().unwrap_or(1));
+        Ok(Position {
+            board,
+            halfmove_clock,
+            fullmove_number,
+        })
+    }
+
+    pub fn to_fen(&self) -> String {
+        let board = self.board.to_string();
+        let fields: Vec<&str> = board.split_whitespace().take(3).collect();
+        // the chess crate prints the square of the pawn that can be taken, FEN wants the
+        // square behind it
+        let en_passant = match self.board.en_passant() {
+            Some(pawn) => pawn.uforward(self.board.side_to_move()).to_string(),
+            None => String::from("-"),
+        };
+        format!(
+            "{} {} {} {}",
+            fields.join(" "),
+            en_passant,
+            self.halfmove_clock,
+            self.fullmove_number
+        )
+    }
+
+    pub fn make_move_new(&self, m: ChessMove) -> Position {
+        Position {
+            board: self.board.make_move_new(m),
+            halfmove_clock: next_halfmove_clock(&self.board, m, self.halfmove_clock),
+            fullmove_number: if self.board.side_to_move() == Color::Black {
+                self.fullmove_number + 1
+            } else {
+                self.fullmove_number
+            },
+        }
+    }
+}
+
+// pawn moves and captures reset the clock
+pub(crate) fn next_halfmove_clock(board: &Board, m: ChessMove, clock: u32) -> u32 {
+    if board.piece_on(m.get_source()) == Some(Piece::Pawn) || board.piece_on(m.get_dest()).is_some()
+    {
+        0
+    } else {
+        clock + 1
+    }
+}
+
+/// How a game has ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// By the side that gave mate.
+    Checkmate(Color),
+    Stalemate,
+    FiftyMoveRule,
+    ThreefoldRepetition,
+}
+
+impl Outcome {
+    /// The result as PGN writes it.
+    pub fn result(self) -> &'static str {
+        match self {
+            Outcome::Checkmate(Color::White) => "1-0",
+            Outcome::Checkmate(Color::Black) => "0-1",
+            _ => "1/2-1/2",
+        }
+    }
+
+    /// Why the game ended, such as "White mates".
+    pub fn reason(self) -> &'static str {
+        match self {
+            Outcome::Checkmate(Color::White) => "White mates",
+            Outcome::Checkmate(Color::Black) => "Black mates",
+            Outcome::Stalemate => "Stalemate",
+            Outcome::FiftyMoveRule => "Fifty move rule",
+            Outcome::ThreefoldRepetition => "Threefold repetition",
+        }
+    }
+}
+
+/// The outcome of a game that has reached the last of `history`, the positions from the start
+/// of the game, or `None` while it goes on.
+pub fn outcome(history: &[Position]) -> Option<Outcome> {
+    let position = history.last()?;
+    let board = &position.board;
+    let repetitions = history
+        .iter()
+        .filter(|p| p.board.get_hash() == board.get_hash())
+        .count();
+    match board.status() {
+        BoardStatus::Checkmate => Some(Outcome::Checkmate(!board.side_to_move())),
+        BoardStatus::Stalemate => Some(Outcome::Stalemate),
+        BoardStatus::Ongoing if position.halfmove_clock >= 100 => Some(Outcome::FiftyMoveRule),
+        BoardStatus::Ongoing if repetitions >= 3 => Some(Outcome::ThreefoldRepetition),
+        BoardStatus::Ongoing => None,
+    }
+}
+
+impl Default for Position {
+    fn default() -> Position {
+        Position::from_fen(START_FEN).unwrap()
+    }
+}
+
+impl From<Board> for Position {
+    fn from(board: Board) -> Position {
+        Position {
+            board,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+        }
+    }
+}
+
+impl FromStr for Position {
+    type Err = String;
+
+    fn from_str(fen: &str) -> Result<Position, String> {
+        Position::from_fen(fen)
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_fen())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(position: Position, moves: &str) -> Position {
+        moves.split_whitespace().fold(position, |position, m| {
+            position.make_move_new(ChessMove::from_str(m).unwrap())
+        })
+    }
+
+    #[test]
+    fn fen_round_trip() {
+        for fen in [
+            START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 37 64",
+            // en passant targets for both sides
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "rnbqkbnr/pppp1ppp/8/8/3Pp3/4P3/PPP2PPP/RNBQKBNR b KQkq d3 0 3",
+        ] {
+            assert_eq!(Position::from_fen(fen).unwrap().to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn en_passant_square_is_behind_the_pawn() {
+        let position = play(Position::default(), "e2e4 a7a6 e4e5 d7d5");
+        assert_eq!(
+            position.to_fen(),
+            "rnbqkbnr/1pp1pppp/p7/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3"
+        );
+        assert_eq!(Position::from_fen(&position.to_fen()).unwrap(), position);
+    }
+
+    #[test]
+    fn outcomes() {
+        let mated = Position::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 1 1").unwrap();
+        assert_eq!(outcome(&[mated]), Some(Outcome::Checkmate(Color::White)));
+        assert_eq!(Outcome::Checkmate(Color::Black).result(), "0-1");
+        let stalemate = Position::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(outcome(&[stalemate]), Some(Outcome::Stalemate));
+        let clock = Position::from_fen("4k3/8/8/8/8/8/8/R3K3 b - - 100 80").unwrap();
+        assert_eq!(outcome(&[clock]), Some(Outcome::FiftyMoveRule));
+        // the start position comes round for the third time
+        let mut history = vec![Position::default()];
+        assert_eq!(outcome(&history), None);
+        for _ in 0..2 {
+            for m in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+                let next = play(*history.last().unwrap(), m);
+                history.push(next);
+            }
+        }
+        assert_eq!(outcome(&history), Some(Outcome::ThreefoldRepetition));
+        assert_eq!(Outcome::ThreefoldRepetition.result(), "1/2-1/2");
+    }
+
+    #[test]
+    fn counters_follow_the_moves() {
+        // a pawn move resets the clock; the number goes up after black's move
+        let position = play(Position::default(), "e2e4");
+        assert_eq!((position.halfmove_clock, position.fullmove_number), (0, 1));
+        let position = play(position, "g8f6 g1f3");
+        assert_eq!((position.halfmove_clock, position.fullmove_number), (2, 2));
+        // so does a capture
+        let position = play(position, "f6e4");
+        assert_eq!((position.halfmove_clock, position.fullmove_number), (0, 3));
+        let position = play(position, "f1c4 b8c6");
+        assert_eq!((position.halfmove_clock, position.fullmove_number), (2, 4));
+        assert!(position.to_fen().ends_with(" 2 4"));
+    }
+}
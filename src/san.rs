@@ -0,0 +1,284 @@
+use chess::{Board, BoardStatus, ChessMove, File, MoveGen, Piece, Rank, Square, EMPTY};
+use std::str::FromStr;
+
+// Standard Algebraic Notation. Writing follows the PGN standard exactly; reading also takes the
+// variants people and other programs produce: 0-0, missing or extra capture marks, e8Q and e8q,
+// lowercase piece letters, over-specified origins such as Ng1f3, and plain coordinates.
+
+fn piece_letter(piece: Piece) -> char {
+    ['P', 'N', 'B', 'R', 'Q', 'K'][piece.to_index()]
+}
+
+fn letter_piece(letter: char) -> Option<Piece> {
+    match letter.to_ascii_uppercase() {
+        'P' => Some(Piece::Pawn),
+        'N' => Some(Piece::Knight),
+        'B' => Some(Piece::Bishop),
+        'R' => Some(Piece::Rook),
+        'Q' => Some(Piece::Queen),
+        'K' => Some(Piece::King),
+        _ => None,
+    }
+}
+
+fn file_char(square: Square) -> char {
+    (b'a' + square.get_file().to_index() as u8) as char
+}
+
+fn rank_char(square: Square) -> char {
+    (b'1' + square.get_rank().to_index() as u8) as char
+}
+
+fn is_castling(board: &Board, m: ChessMove) -> bool {
+    let from = m.get_source().get_file().to_index();
+    board.piece_on(m.get_source()) == Some(Piece::King)
+        && from.abs_diff(m.get_dest().get_file().to_index()) == 2
+}
+
+/// `m` in Standard Algebraic Notation, with the least disambiguation that makes it unique and a
+/// `+` or `#` suffix. `m` must be legal in `board`.
+pub fn to_san(board: &Board, m: ChessMove) -> String {
+    let source = m.get_source();
+    let dest = m.get_dest();
+    let piece = board.piece_on(source).unwrap_or(Piece::Pawn);
+    let capture = board.piece_on(dest).is_some()
+        || (piece == Piece::Pawn && source.get_file() != dest.get_file());
+    let mut text = String::new();
+    if is_castling(board, m) {
+        let short = dest.get_file() == File::G;
+        text.push_str(if short { "O-O" } else { "O-O-O" });
+    } else {
+        if piece == Piece::Pawn {
+            if capture {
+                text.push(file_char(source));
+            }
+        } else {
+            text.push(piece_letter(piece));
+            // other pieces of the same kind that could go to the same square
+            let others: Vec<Square> = MoveGen::new_legal(board)
+                .filter(|o| o.get_dest() == dest && o.get_source() != source)
+                .filter(|o| board.piece_on(o.get_source()) == Some(piece))
+                .map(|o| o.get_source())
+                .collect();
+            if !others.is_empty() {
+                if others.iter().all(|o| o.get_file() != source.get_file()) {
+                    text.push(file_char(source));
+                } else if others.iter().all(|o| o.get_rank() != source.get_rank()) {
+                    text.push(rank_char(source));
+                } else {
+                    text.push(file_char(source));
+                    text.push(rank_char(source));
+                }
+            }
+        }
+        if capture {
+            text.push('x');
+        }
+        text.push_str(&dest.to_string());
+        if let Some(promotion) = m.get_promotion() {
+            text.push('=');
+            text.push(piece_letter(promotion));
+        }
+    }
+    let after = board.make_move_new(m);
+    if after.status() == BoardStatus::Checkmate {
+        text.push('#');
+    } else if *after.checkers() != EMPTY {
+        text.push('+');
+    }
+    text
+}
+
+/// The legal move in `board` that `text` names, in SAN or in coordinates. Check marks,
+/// annotations such as `!?`, capture marks and redundant origin squares are not checked.
+pub fn parse_san(board: &Board, text: &str) -> Result<ChessMove, String> {
+    let error = |reason: &str| Err(format!("{} move '{}'", reason, text));
+    let san = text
+        .trim()
+        .trim_end_matches("e.p.")
+        .trim_end()
+        .trim_end_matches(['+', '#', '!', '?']);
+    if let Ok(m) = ChessMove::from_str(san) {
+        return if board.legal(m) {
+            Ok(m)
+        } else {
+            error("illegal")
+        };
+    }
+
+    let castling = match san {
+        "O-O" | "0-0" | "o-o" => Some(File::G),
+        "O-O-O" | "0-0-0" | "o-o-o" => Some(File::C),
+        _ => None,
+    };
+    if let Some(file) = castling {
+        let king = board.king_square(board.side_to_move());
+        let dest = Square::make_square(king.get_rank(), file);
+        return MoveGen::new_legal(board)
+            .find(|&m| m.get_source() == king && m.get_dest() == dest && is_castling(board, m))
+            .map_or_else(|| error("illegal"), Ok);
+    }
+
+    // strip the marks between the parts, keeping piece, origin, destination and promotion
+    let mut chars: Vec<char> = san.chars().filter(|c| !"x:-=".contains(*c)).collect();
+    // a lowercase b is a pawn on the b-file, any other piece letter may be lowercase
+    let piece = match chars.first() {
+        Some(&c) if c != 'b' && !c.is_ascii_digit() => letter_piece(c),
+        _ => None,
+    };
+    if piece.is_some() {
+        chars.remove(0);
+    }
+    let piece = piece.unwrap_or(Piece::Pawn);
+    let promotion = match chars.last() {
+        Some(&c) if piece == Piece::Pawn && !c.is_ascii_digit() => {
+            chars.pop();
+            match letter_piece(c) {
+                Some(p) if p != Piece::Pawn && p != Piece::King => Some(p),
+                _ => return error("invalid"),
+            }
+        }
+        _ => None,
+    };
+    if chars.len() < 2 || chars.len() > 4 {
+        return error("invalid");
+    }
+    let dest: String = chars[chars.len() - 2..].iter().collect();
+    let Ok(dest) = Square::from_str(&dest) else {
+        return error("invalid");
+    };
+    let mut origin_file = None;
+    let mut origin_rank = None;
+    for &c in &chars[..chars.len() - 2] {
+        match c {
+            'a'..='h' => origin_file = Some(File::from_index(c as usize - 'a' as usize)),
+            '1'..='8' => origin_rank = Some(Rank::from_index(c as usize - '1' as usize)),
+            _ => return error("invalid"),
+        }
+    }
+
+    let candidates: Vec<ChessMove> = MoveGen::new_legal(board)
+        .filter(|m| m.get_dest() == dest && board.piece_on(m.get_source()) == Some(piece))
+        .filter(|m| origin_file.is_none_or(|f| m.get_source().get_file() == f))
+        .filter(|m| origin_rank.is_none_or(|r| m.get_source().get_rank() == r))
+        .filter(|m| m.get_promotion() == promotion && !is_castling(board, *m))
+        .collect();
+    match candidates.as_slice() {
+        [m] => Ok(*m),
+        [] => error("illegal"),
+        _ => error("ambiguous"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Position;
+
+    fn board(fen: &str) -> Board {
+        Position::from_fen(fen).unwrap().board
+    }
+
+    fn m(text: &str) -> ChessMove {
+        ChessMove::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn writes_san() {
+        let start = Board::default();
+        assert_eq!(to_san(&start, m("g1f3")), "Nf3");
+        assert_eq!(to_san(&start, m("e2e4")), "e4");
+        let castle = board("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        assert_eq!(to_san(&castle, m("e1g1")), "O-O");
+        assert_eq!(to_san(&castle, m("e1c1")), "O-O-O");
+        assert_eq!(
+            to_san(&board("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"), m("a1a8")),
+            "Ra8#"
+        );
+        assert_eq!(
+            to_san(&board("4k3/8/8/8/8/8/8/R3K3 w - - 0 1"), m("a1a8")),
+            "Ra8+"
+        );
+        let promotion = board("3r4/4P3/8/8/8/8/k7/4K3 w - - 0 1");
+        assert_eq!(to_san(&promotion, m("e7e8q")), "e8=Q");
+        assert_eq!(to_san(&promotion, m("e7d8n")), "exd8=N");
+        let en_passant = board("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1");
+        assert_eq!(to_san(&en_passant, m("e5d6")), "exd6");
+        assert_eq!(parse_san(&en_passant, "exd6 e.p."), Ok(m("e5d6")));
+    }
+
+    #[test]
+    fn disambiguates() {
+        // knights on b1 and f3 both reach d2, rooks on a1 and a3 both reach a2
+        let position = board("4k3/8/8/8/8/R4N2/8/RN2K3 w - - 0 1");
+        assert_eq!(to_san(&position, m("b1d2")), "Nbd2");
+        assert_eq!(to_san(&position, m("a1a2")), "R1a2");
+        // with a rival on the same file and one on the same rank both are needed
+        let queens = board("8/8/k7/8/4Q2Q/8/8/K6Q w - - 0 1");
+        assert_eq!(to_san(&queens, m("h4e1")), "Qh4e1");
+        assert_eq!(to_san(&queens, m("e4e1")), "Qee1");
+        assert_eq!(to_san(&queens, m("h1e1")), "Q1e1");
+        // a pinned knight is no rival
+        let pinned = board("4k3/8/8/8/1b6/8/3N4/4K1N1 w - - 0 1");
+        assert_eq!(to_san(&pinned, m("g1f3")), "Nf3");
+    }
+
+    #[test]
+    fn reads_its_own_output() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/8/k7/8/4Q2Q/8/8/K6Q w - - 0 1",
+        ];
+        for fen in fens {
+            let board = board(fen);
+            for m in MoveGen::new_legal(&board) {
+                let san = to_san(&board, m);
+                assert_eq!(parse_san(&board, &san), Ok(m), "{} in {}", san, fen);
+            }
+        }
+    }
+
+    #[test]
+    fn reads_sloppy_san() {
+        let start = Board::default();
+        for text in [
+            "Nf3", "Ng1f3", "Ng1-f3", "nf3", "Nf3!?", "g1f3", "N1f3", "Ngf3",
+        ] {
+            assert_eq!(parse_san(&start, text), Ok(m("g1f3")), "{}", text);
+        }
+        let position = board("r3k2r/3n4/8/3p4/4P3/8/1p6/R3K2R b KQkq - 0 1");
+        assert_eq!(parse_san(&position, "0-0"), Ok(m("e8g8")));
+        assert_eq!(parse_san(&position, "O-O-O"), Ok(m("e8c8")));
+        assert_eq!(parse_san(&position, "dxe4"), Ok(m("d5e4")));
+        assert_eq!(parse_san(&position, "de4"), Ok(m("d5e4")));
+        assert_eq!(parse_san(&position, "d5xe4"), Ok(m("d5e4")));
+        assert_eq!(parse_san(&position, "Nb6"), Ok(m("d7b6")));
+        assert_eq!(parse_san(&position, "Nxb6"), Ok(m("d7b6")));
+        for text in ["b1=Q", "b1Q", "b1q", "b1=Q+"] {
+            assert_eq!(parse_san(&position, text), Ok(m("b2b1q")), "{}", text);
+        }
+        assert_eq!(parse_san(&position, "bxa1=N"), Ok(m("b2a1n")));
+    }
+
+    #[test]
+    fn rejects_bad_moves() {
+        let start = Board::default();
+        assert!(parse_san(&start, "e5").is_err());
+        assert!(parse_san(&start, "Nf4").is_err());
+        assert!(parse_san(&start, "O-O").is_err());
+        assert!(parse_san(&start, "e2e5").is_err());
+        assert!(parse_san(&start, "").is_err());
+        assert!(parse_san(&start, "Zf3").is_err());
+        // a promotion needs its piece
+        let promotion = board("8/4P3/8/8/8/8/k7/4K3 w - - 0 1");
+        assert!(parse_san(&promotion, "e8").is_err());
+        assert!(parse_san(&promotion, "e8=K").is_err());
+        let rooks = board("4k3/8/8/8/8/8/4K3/R6R w - - 0 1");
+        assert_eq!(
+            parse_san(&rooks, "Rd1"),
+            Err(String::from("ambiguous move 'Rd1'"))
+        );
+    }
+}
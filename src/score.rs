@@ -0,0 +1,232 @@
+use chess::Color;
+use std::fmt;
+use std::ops::Neg;
+
+// Scores are a single integer from the point of view of one side, so the search can negate them
+// and compare them directly. Mates are stored as MATE minus the distance in plies, which sorts a
+// shorter mate above a longer one and a later mate against us above an earlier one.
+
+/// Centipawns or a forced mate, from one side's point of view. Scores handed out by the engine
+/// count from the side to move; [`evaluate`](crate::Engine::evaluate) counts the same way.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Score(i32);
+
+impl Score {
+    /// The score of delivering mate right now; a mate in `n` plies is `MATE - n`.
+    pub const MATE: i32 = 32_000;
+    /// Longest mate distance that can be represented.
+    pub const MAX_PLY: i32 = 256;
+    /// Above every real score, for the bounds of a search window.
+    pub const INFINITE: Score = Score(Score::MATE + 1);
+    pub const DRAW: Score = Score(0);
+
+    // largest evaluation that cannot be mistaken for a mate
+    const MAX_CENTIPAWNS: i32 = Score::MATE - Score::MAX_PLY - 1;
+
+    /// An evaluation in centipawns, clamped below the mate scores.
+    pub fn cp(centipawns: i32) -> Score {
+        Score(centipawns.clamp(-Score::MAX_CENTIPAWNS, Score::MAX_CENTIPAWNS))
+    }
+
+    /// Mate delivered `plies` plies from now.
+    pub const fn mate_in_plies(plies: i32) -> Score {
+        Score(Score::MATE - plies)
+    }
+
+    /// Mated `plies` plies from now.
+    pub const fn mated_in_plies(plies: i32) -> Score {
+        Score(plies - Score::MATE)
+    }
+
+    /// The score for `color` of a score from white's point of view.
+    pub fn for_side(self, color: Color) -> Score {
+        if color == Color::White {
+            self
+        } else {
+            -self
+        }
+    }
+
+    pub fn is_mate(self) -> bool {
+        self.0.abs() > Score::MAX_CENTIPAWNS && self.0.abs() <= Score::MATE
+    }
+
+    /// The evaluation in centipawns, 0 for mates.
+    pub fn centipawns(self) -> i32 {
+        if self.is_mate() {
+            0
+        } else {
+            self.0
+        }
+    }
+
+    /// Plies until mate, positive when this side mates and negative when it is mated.
+    pub fn mate_plies(self) -> Option<i32> {
+        if !self.is_mate() {
+            None
+        } else if self.0 > 0 {
+            Some(Score::MATE - self.0)
+        } else {
+            Some(-(Score::MATE + self.0))
+        }
+    }
+
+    /// Moves until mate, signed like [`Score::mate_plies`].
+    pub fn mate_in(self) -> Option<i32> {
+        self.mate_plies()
+            .map(|plies| (plies.abs() + 1) / 2 * plies.signum())
+    }
+
+    /// The score as UCI prints it, `cp <centipawns>` or `mate <moves>`.
+    pub fn to_uci(self) -> String {
+        match self.mate_in() {
+            Some(moves) => format!("mate {}", moves),
+            None => format!("cp {}", self.0),
+        }
+    }
+
+    /// Converts a mate score counted from the root into one counted from a node `ply` plies
+    /// deep, which is how a transposition table has to store it to reuse it elsewhere.
+    pub fn to_tt(self, ply: i32) -> Score {
+        match self.0 {
+            v if self.is_mate() && v > 0 => Score(v + ply),
+            v if self.is_mate() => Score(v - ply),
+            v => Score(v),
+        }
+    }
+
+    /// The inverse of [`Score::to_tt`] for a node `ply` plies deep.
+    pub fn from_tt(self, ply: i32) -> Score {
+        match self.0 {
+            v if self.is_mate() && v > 0 => Score(v - ply),
+            v if self.is_mate() => Score(v + ply),
+            v => Score(v),
+        }
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+
+    fn neg(self) -> Score {
+        Score(-self.0)
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mate_in() {
+            Some(moves) => write!(f, "#{}", moves),
+            None => write!(f, "{:+.2}", self.0 as f64 / 100.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Ordering;
+
+    // every mate distance and a spread of evaluations, including the clamped extremes
+    fn samples() -> Vec<Score> {
+        let mut scores = vec![Score::INFINITE, -Score::INFINITE, Score::DRAW];
+        for plies in 0..=Score::MAX_PLY {
+            scores.push(Score::mate_in_plies(plies));
+            scores.push(Score::mated_in_plies(plies));
+        }
+        let mut x: u64 = 1;
+        for _ in 0..200 {
+            x = x
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            scores.push(Score::cp((x >> 33) as i32 % 40_000 - 20_000));
+        }
+        scores.push(Score::cp(i32::MAX));
+        scores.push(Score::cp(i32::MIN));
+        scores
+    }
+
+    #[test]
+    fn ordering_is_total_and_consistent() {
+        let scores = samples();
+        for &a in &scores {
+            for &b in &scores {
+                // exactly one of <, ==, > holds, and negation reverses it
+                let relations = [a < b, a == b, a > b];
+                assert_eq!(relations.iter().filter(|&&r| r).count(), 1);
+                assert_eq!(a.cmp(&b), (-b).cmp(&-a));
+                assert_eq!(a.partial_cmp(&b), Some(a.cmp(&b)));
+            }
+        }
+        let mut sorted = scores.clone();
+        sorted.sort();
+        for pair in sorted.windows(2) {
+            assert!(pair[0] <= pair[1]);
+        }
+    }
+
+    #[test]
+    fn mates_sort_by_distance() {
+        let evaluations = samples()
+            .into_iter()
+            .filter(|s| !s.is_mate() && s.0.abs() <= Score::MATE);
+        for eval in evaluations {
+            assert!(Score::mate_in_plies(Score::MAX_PLY) > eval);
+            assert!(Score::mated_in_plies(Score::MAX_PLY) < eval);
+        }
+        for plies in 0..Score::MAX_PLY {
+            assert!(Score::mate_in_plies(plies) > Score::mate_in_plies(plies + 1));
+            assert!(Score::mated_in_plies(plies) < Score::mated_in_plies(plies + 1));
+            assert_eq!(-Score::mate_in_plies(plies), Score::mated_in_plies(plies));
+        }
+    }
+
+    #[test]
+    fn mates_rank_beyond_evaluations_for_both_sides() {
+        // seen from white, a mate by black is worse than any evaluation and a mate by white
+        // better, whichever side of the comparison the mate is on
+        let evaluations = [
+            Score::cp(-Score::MAX_CENTIPAWNS),
+            Score::DRAW,
+            Score::cp(Score::MAX_CENTIPAWNS),
+        ];
+        for eval in evaluations {
+            for plies in [1, 6, Score::MAX_PLY] {
+                let white_mates = Score::mate_in_plies(plies).for_side(Color::White);
+                let black_mates = Score::mate_in_plies(plies).for_side(Color::Black);
+                assert_eq!(white_mates.cmp(&eval), Ordering::Greater);
+                assert_eq!(eval.cmp(&white_mates), Ordering::Less);
+                assert_eq!(black_mates.cmp(&eval), Ordering::Less);
+                assert_eq!(eval.cmp(&black_mates), Ordering::Greater);
+            }
+        }
+    }
+
+    #[test]
+    fn mate_distances() {
+        assert_eq!(Score::mate_in_plies(1).mate_in(), Some(1));
+        assert_eq!(Score::mate_in_plies(5).mate_in(), Some(3));
+        assert_eq!(Score::mated_in_plies(4).mate_in(), Some(-2));
+        assert_eq!(Score::mated_in_plies(4).mate_plies(), Some(-4));
+        assert_eq!(Score::cp(35).mate_in(), None);
+        assert_eq!(Score::mate_in_plies(3).to_uci(), "mate 2");
+        assert_eq!(Score::cp(-42).to_uci(), "cp -42");
+        assert_eq!(Score::cp(1_000_000).centipawns(), Score::MAX_CENTIPAWNS);
+        assert!(!Score::cp(1_000_000).is_mate());
+        assert!(!Score::INFINITE.is_mate());
+    }
+
+    #[test]
+    fn transposition_table_round_trip() {
+        for score in samples().into_iter().filter(|s| s.0.abs() <= Score::MATE) {
+            // a mate found from the root cannot be closer to the root than the node storing it
+            for ply in 0..=score.mate_plies().map_or(20, |plies| plies.abs()) {
+                assert_eq!(score.to_tt(ply).from_tt(ply), score);
+            }
+        }
+        // a mate in 5 from the root is a mate in 3 from a node two plies down
+        let stored = Score::mate_in_plies(5).to_tt(2);
+        assert_eq!(stored.mate_plies(), Some(3));
+        assert_eq!(stored.from_tt(4), Score::mate_in_plies(7));
+    }
+}
@@ -0,0 +1,108 @@
+use chess::{get_pawn_attacks, BitBoard, Board, Color, Piece, EMPTY};
+
+use crate::attacks::AttackMaps;
+use crate::weights::Term;
+
+// Threats: pieces that can be won or have to move. Everything is counted for the side making
+// the threat.
+
+// pieces of lower value than the piece attacked, which win material even when it is defended
+fn lesser_pieces(victim: Piece) -> &'static [Piece] {
+    match victim {
+        Piece::Knight | Piece::Bishop => &[Piece::Pawn],
+        Piece::Rook => &[Piece::Pawn, Piece::Knight, Piece::Bishop],
+        Piece::Queen => &[Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook],
+        Piece::Pawn | Piece::King => &[],
+    }
+}
+
+// squares the side's pawns can advance to with a single step
+fn pawn_pushes(board: &Board, color: Color) -> BitBoard {
+    let pawns = *board.pieces(Piece::Pawn) & *board.color_combined(color);
+    let pushed = if color == Color::White {
+        BitBoard(pawns.0 << 8)
+    } else {
+        BitBoard(pawns.0 >> 8)
+    };
+    pushed & !board.combined()
+}
+
+pub(crate) fn threats(board: &Board, maps: &AttackMaps, mut add: impl FnMut(Term, Color, i32)) {
+    for color in [Color::White, Color::Black] {
+        let enemy = !color;
+        let theirs = *board.color_combined(enemy);
+        let attacked = maps.side(color);
+        let defended = maps.side(enemy);
+        let pieces = theirs & !board.pieces(Piece::Pawn) & !board.pieces(Piece::King);
+
+        for victim in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+            let attackers = lesser_pieces(victim)
+                .iter()
+                .fold(EMPTY, |all, piece| all | maps.pieces(color, *piece));
+            let count = (*board.pieces(victim) & theirs & attackers).popcnt();
+            add(Term::ThreatByLesser(victim), color, count as i32);
+        }
+
+        add(
+            Term::Hanging,
+            color,
+            (pieces & attacked & !defended).popcnt() as i32,
+        );
+
+        // a push to a square the enemy pawns do not cover, which is either defended or not
+        // attacked at all, and from which the pawn would attack a piece
+        let safe = !maps.pieces(enemy, Piece::Pawn) & (attacked | !defended);
+        let mut push_threats = EMPTY;
+        for square in pawn_pushes(board, color) & safe {
+            push_threats |= get_pawn_attacks(square, color, pieces);
+        }
+        add(Term::PawnPushThreat, color, push_threats.popcnt() as i32);
+
+        // contested squares the enemy cannot hold with a pawn limit where its pieces can go
+        let restricted = attacked & defended & !maps.pieces(enemy, Piece::Pawn);
+        add(Term::Restricted, color, restricted.popcnt() as i32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn count(fen: &str, wanted: Term, color: Color) -> i32 {
+        let board = Board::from_str(fen).unwrap();
+        let maps = AttackMaps::new(&board);
+        let mut total = 0;
+        threats(&board, &maps, |term, c, count| {
+            if term == wanted && c == color {
+                total += count;
+            }
+        });
+        total
+    }
+
+    #[test]
+    fn pawn_attacking_a_defended_knight() {
+        let fen = "4k3/1p6/2n1p3/3P4/8/8/8/4K3 w - - 0 1";
+        assert_eq!(
+            count(fen, Term::ThreatByLesser(Piece::Knight), Color::White),
+            1
+        );
+        assert_eq!(count(fen, Term::Hanging, Color::White), 0);
+    }
+
+    #[test]
+    fn undefended_piece_is_hanging() {
+        let fen = "4k3/8/8/3r4/8/8/8/3RK3 w - - 0 1";
+        assert_eq!(count(fen, Term::Hanging, Color::White), 1);
+        // the white rook is attacked too, but the king defends it
+        assert_eq!(count(fen, Term::Hanging, Color::Black), 0);
+    }
+
+    #[test]
+    fn pawn_push_threatens_two_pieces() {
+        // d3-d4 would fork the knights on c5 and e5
+        let fen = "4k3/8/8/2n1n3/8/3P4/8/4K3 w - - 0 1";
+        assert_eq!(count(fen, Term::PawnPushThreat, Color::White), 2);
+    }
+}
@@ -0,0 +1,118 @@
+use chess::{Board, BoardStatus, Color};
+
+use crate::engine::{evaluate, game_phase, taper, Trace, MAX_PHASE};
+use crate::weights::{EvalParams, Term};
+
+// Breakdown of the evaluation by term for the `eval` command. The numbers are collected as a
+// Trace of the real evaluator, so the table and the score the search uses cannot disagree.
+
+struct Row {
+    category: &'static str,
+    // middlegame and endgame contribution for white and black, each from its own side's view
+    white: (i32, i32),
+    black: (i32, i32),
+}
+
+struct Breakdown<'a> {
+    params: &'a EvalParams,
+    rows: Vec<Row>,
+}
+
+impl Trace for Breakdown<'_> {
+    fn add(&mut self, term: Term, color: Color, count: i32) {
+        let weight = self.params.weight(term);
+        let category = term.category();
+        let index = match self.rows.iter().position(|r| r.category == category) {
+            Some(index) => index,
+            None => {
+                self.rows.push(Row {
+                    category,
+                    white: (0, 0),
+                    black: (0, 0),
+                });
+                self.rows.len() - 1
+            }
+        };
+        let side = if color == Color::White {
+            &mut self.rows[index].white
+        } else {
+            &mut self.rows[index].black
+        };
+        side.0 += weight.mg * count;
+        side.1 += weight.eg * count;
+    }
+}
+
+fn pawns(centipawns: i32) -> String {
+    format!("{:+.2}", centipawns as f64 / 100.0)
+}
+
+// the table as printed, one line per element
+pub fn eval_table(board: &Board, params: &EvalParams) -> Vec<String> {
+    match board.status() {
+        BoardStatus::Checkmate => {
+            let winner = if board.side_to_move() == Color::White {
+                "black"
+            } else {
+                "white"
+            };
+            return vec![format!("checkmate, {} wins", winner)];
+        }
+        BoardStatus::Stalemate => return vec![String::from("stalemate, draw")],
+        BoardStatus::Ongoing => {}
+    }
+
+    let mut breakdown = Breakdown {
+        params,
+        rows: Vec::new(),
+    };
+    let score = evaluate(board, params, &mut breakdown);
+    let phase = game_phase(board);
+
+    let mut lines = vec![
+        format!(
+            "{:<20} | {:>7} {:>7} | {:>7} {:>7} | {:>7} {:>7}",
+            "Term", "White", "", "Black", "", "Total", ""
+        ),
+        format!(
+            "{:<20} | {:>7} {:>7} | {:>7} {:>7} | {:>7} {:>7}",
+            "", "MG", "EG", "MG", "EG", "MG", "EG"
+        ),
+        "-".repeat(72),
+    ];
+    let (mut mg, mut eg) = (0, 0);
+    for row in &breakdown.rows {
+        let total = (row.white.0 - row.black.0, row.white.1 - row.black.1);
+        mg += total.0;
+        eg += total.1;
+        lines.push(format!(
+            "{:<20} | {:>7} {:>7} | {:>7} {:>7} | {:>7} {:>7}",
+            row.category,
+            pawns(row.white.0),
+            pawns(row.white.1),
+            pawns(row.black.0),
+            pawns(row.black.1),
+            pawns(total.0),
+            pawns(total.1)
+        ));
+    }
+    lines.push("-".repeat(72));
+    lines.push(format!(
+        "{:<20} | {:>7} {:>7} | {:>7} {:>7} | {:>7} {:>7}",
+        "Total",
+        "",
+        "",
+        "",
+        "",
+        pawns(mg),
+        pawns(eg)
+    ));
+    lines.push(String::new());
+    lines.push(format!("Phase: {}/{}", phase, MAX_PHASE));
+    debug_assert_eq!(taper(mg, eg, phase), score.centipawns());
+    lines.push(format!(
+        "Final evaluation: {} (white side)",
+        pawns(score.centipawns())
+    ));
+    lines
+}
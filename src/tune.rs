@@ -0,0 +1,406 @@
+use chess::{Board, BoardStatus, Color};
+use std::collections::HashMap;
+use std::fs;
+
+use crate::datagen;
+use crate::engine::{evaluate, game_phase, NoTrace, Trace, MAX_PHASE};
+use crate::fen::parse_fen;
+use crate::weights::{EvalParams, Phase, Term};
+
+// Texel tuning: the evaluation is linear in its weights, so each training position is reduced
+// to its weight coefficients once and the weights are then fitted to the game results through
+// a sigmoid of the evaluation with Adam.
+
+const LEARNING_RATE: f64 = 1.0;
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+const EPSILON: f64 = 1e-8;
+
+// every value of the weights file flattened into one vector, in file order
+struct Layout {
+    offsets: HashMap<&'static str, usize>,
+    middlegame: Vec<bool>,
+}
+
+impl Layout {
+    fn new(params: &EvalParams) -> Layout {
+        let mut offsets = HashMap::new();
+        let mut middlegame = Vec::new();
+        for (name, entry) in params.clone().entries() {
+            offsets.insert(name, middlegame.len());
+            for i in 0..entry.len() {
+                middlegame.push(entry.phase(i) == Phase::Middlegame);
+            }
+        }
+        Layout {
+            offsets,
+            middlegame,
+        }
+    }
+
+    fn indices(&self, term: Term) -> [usize; 2] {
+        term.locations()
+            .map(|(name, offset)| self.offsets[name] + offset)
+    }
+
+    // scales a raw count by the phase weight of the value it applies
+    fn phase_factor(&self, index: usize, phase: f64) -> f64 {
+        if self.middlegame[index] {
+            phase
+        } else {
+            1.0 - phase
+        }
+    }
+}
+
+fn to_vector(params: &EvalParams) -> Vec<f64> {
+    let mut vector = Vec::new();
+    for (_, entry) in params.clone().entries() {
+        vector.extend((0..entry.len()).map(|i| entry.get(i) as f64));
+    }
+    vector
+}
+
+fn from_vector(initial: &EvalParams, vector: &[f64]) -> EvalParams {
+    let mut params = initial.clone();
+    let mut index = 0;
+    for (_, mut entry) in params.entries() {
+        for i in 0..entry.len() {
+            entry.set(i, vector[index].round() as i32);
+            index += 1;
+        }
+    }
+    params
+}
+
+struct Sample {
+    // white-minus-black count per value, each already scaled by its phase weight
+    features: Vec<(u32, f32)>,
+    result: f64,
+}
+
+struct Features<'a> {
+    layout: &'a Layout,
+    phase: f64,
+    counts: Vec<(u32, f32)>,
+}
+
+impl Trace for Features<'_> {
+    fn add(&mut self, term: Term, color: Color, count: i32) {
+        let signed = if color == Color::White { count } else { -count };
+        for index in self.layout.indices(term) {
+            let coefficient = signed as f64 * self.layout.phase_factor(index, self.phase);
+            match self.counts.iter_mut().find(|(i, _)| *i == index as u32) {
+                Some((_, c)) => *c += coefficient as f32,
+                None => self.counts.push((index as u32, coefficient as f32)),
+            }
+        }
+    }
+}
+
+impl Sample {
+    fn new(board: &Board, layout: &Layout, params: &EvalParams, result: f64) -> Sample {
+        let mut features = Features {
+            layout,
+            phase: game_phase(board) as f64 / MAX_PHASE as f64,
+            counts: Vec::new(),
+        };
+        evaluate(board, params, &mut features);
+        features.counts.retain(|(_, c)| *c != 0.0);
+        Sample {
+            features: features.counts,
+            result,
+        }
+    }
+
+    fn evaluate(&self, params: &[f64]) -> f64 {
+        self.features
+            .iter()
+            .map(|&(i, c)| params[i as usize] * c as f64)
+            .sum()
+    }
+}
+
+fn sigmoid(k: f64, eval: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval / 400.0))
+}
+
+fn error(samples: &[Sample], params: &[f64], k: f64) -> f64 {
+    let total: f64 = samples
+        .iter()
+        .map(|e| (e.result - sigmoid(k, e.evaluate(params))).powi(2))
+        .sum();
+    total / samples.len() as f64
+}
+
+// the scaling constant that best maps the untuned evaluation onto the results
+fn find_k(samples: &[Sample], params: &[f64]) -> f64 {
+    let (mut low, mut high) = (0.0, 5.0);
+    for _ in 0..50 {
+        let a = low + (high - low) / 3.0;
+        let b = high - (high - low) / 3.0;
+        if error(samples, params, a) < error(samples, params, b) {
+            high = b;
+        } else {
+            low = a;
+        }
+    }
+    (low + high) / 2.0
+}
+
+fn gradient(samples: &[Sample], params: &[f64], k: f64) -> Vec<f64> {
+    let mut gradient = vec![0.0; params.len()];
+    let scale = k * std::f64::consts::LN_10 / 400.0;
+    for sample in samples {
+        let s = sigmoid(k, sample.evaluate(params));
+        let common = -2.0 * (sample.result - s) * s * (1.0 - s) * scale;
+        for &(i, c) in &sample.features {
+            gradient[i as usize] += common * c as f64;
+        }
+    }
+    let n = samples.len() as f64;
+    gradient.iter().map(|g| g / n).collect()
+}
+
+// the moment estimates of Adam, one per weight
+struct Adam {
+    m: Vec<f64>,
+    v: Vec<f64>,
+    t: i32,
+}
+
+impl Adam {
+    fn new(len: usize) -> Adam {
+        Adam {
+            m: vec![0.0; len],
+            v: vec![0.0; len],
+            t: 0,
+        }
+    }
+
+    fn step(&mut self, params: &mut [f64], gradient: &[f64]) {
+        self.t += 1;
+        for i in 0..params.len() {
+            let g = gradient[i];
+            self.m[i] = BETA1 * self.m[i] + (1.0 - BETA1) * g;
+            self.v[i] = BETA2 * self.v[i] + (1.0 - BETA2) * g * g;
+            let m_hat = self.m[i] / (1.0 - BETA1.powi(self.t));
+            let v_hat = self.v[i] / (1.0 - BETA2.powi(self.t));
+            params[i] -= LEARNING_RATE * m_hat / (v_hat.sqrt() + EPSILON);
+        }
+    }
+}
+
+// game result from white's point of view
+fn parse_result(token: &str) -> Option<f64> {
+    match token.trim_matches(|c| c == '[' || c == ']' || c == '"' || c == ';') {
+        "1-0" | "1.0" | "1" => Some(1.0),
+        "0-1" | "0.0" | "0" => Some(0.0),
+        "1/2-1/2" | "0.5" => Some(0.5),
+        _ => None,
+    }
+}
+
+// accepts "<fen> [1.0]", "<fen> \"1-0\";" and "<fen> 0.5", with or without the move counters,
+// and the "<fen> | <score> | <result>" lines written by datagen
+fn parse_line(line: &str) -> Option<(Board, f64)> {
+    if let [fen, .., result] = line.split('|').collect::<Vec<_>>()[..] {
+        return Some((parse_fen(fen.trim()).ok()?, parse_result(result.trim())?));
+    }
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let result = parse_result(tokens.last()?)?;
+    if tokens.len() < 5 {
+        return None;
+    }
+    let counters = tokens[4..tokens.len() - 1]
+        .iter()
+        .take(2)
+        .take_while(|t| t.parse::<u32>().is_ok())
+        .count();
+    let board = parse_fen(&tokens[..4 + counters].join(" ")).ok()?;
+    Some((board, result))
+}
+
+// the linear model drops the rounding of the real tapered evaluation, so the largest
+// difference between the two is reported as a sanity check
+fn load(
+    path: &str,
+    layout: &Layout,
+    weights: &EvalParams,
+    params: &[f64],
+) -> Result<Vec<Sample>, String> {
+    let positions: Vec<Option<(Board, f64)>> = if path.ends_with(".bin") {
+        let bytes = fs::read(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        if bytes.len() % datagen::RECORD_SIZE != 0 {
+            return Err(format!("{} is not a whole number of records", path));
+        }
+        bytes
+            .chunks(datagen::RECORD_SIZE)
+            .map(|record| {
+                let sample = datagen::decode(record).ok()?;
+                Some((sample.position.board, sample.result))
+            })
+            .collect()
+    } else {
+        let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        text.lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(parse_line)
+            .collect()
+    };
+    let mut samples = Vec::new();
+    let mut skipped = 0;
+    let mut worst: f64 = 0.0;
+    for position in positions {
+        match position {
+            Some((board, result)) if board.status() == BoardStatus::Ongoing => {
+                let sample = Sample::new(&board, layout, weights, result);
+                let real = evaluate(&board, weights, &mut NoTrace).centipawns() as f64;
+                worst = worst.max((sample.evaluate(params) - real).abs());
+                samples.push(sample);
+            }
+            _ => skipped += 1,
+        }
+    }
+    println!("loaded {} positions, skipped {}", samples.len(), skipped);
+    println!("largest difference to the real evaluation: {:.1}", worst);
+    if samples.is_empty() {
+        return Err(String::from("no usable positions"));
+    }
+    Ok(samples)
+}
+
+// starts from `initial` and writes the tuned weights in the format EvalParams::load reads
+pub fn tune(
+    path: &str,
+    initial: &EvalParams,
+    iterations: usize,
+    output: &str,
+) -> Result<(), String> {
+    let layout = Layout::new(initial);
+    let mut params = to_vector(initial);
+    let samples = load(path, &layout, initial, &params)?;
+    let k = find_k(&samples, &params);
+    println!(
+        "k = {:.4}, initial error {:.6}",
+        k,
+        error(&samples, &params, k)
+    );
+
+    let mut adam = Adam::new(params.len());
+    for t in 1..=iterations {
+        let g = gradient(&samples, &params, k);
+        adam.step(&mut params, &g);
+        if t % 50 == 0 || t == iterations {
+            println!("iteration {} error {:.6}", t, error(&samples, &params, k));
+        }
+    }
+
+    from_vector(initial, &params).save(output)?;
+    println!("weights written to {}", output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datagen::{encode, Sample as DataSample};
+    use crate::position::Position;
+
+    const FEN: &str = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+
+    #[test]
+    fn reads_every_line_format() {
+        let board = parse_fen(FEN).unwrap();
+        let placement = FEN.rsplitn(3, ' ').last().unwrap();
+        for (line, result) in [
+            (format!("{} [1.0]", FEN), 1.0),
+            (format!("{} \"1-0\";", FEN), 1.0),
+            (format!("{} 0.5", FEN), 0.5),
+            (format!("{} [0-1]", placement), 0.0),
+            (
+                format!("{} 2 1/2-1/2", FEN.rsplit_once(' ').unwrap().0),
+                0.5,
+            ),
+            (format!("{} | -35 | 0.0", FEN), 0.0),
+            (format!("{} | 1.0", placement), 1.0),
+        ] {
+            assert_eq!(parse_line(&line), Some((board, result)), "{}", line);
+        }
+        for line in [FEN, "not a fen 1.0", &format!("{} 2.0", FEN)] {
+            assert_eq!(parse_line(line), None, "{}", line);
+        }
+    }
+
+    #[test]
+    fn loads_text_and_binary_data() {
+        let params = EvalParams::default();
+        let layout = Layout::new(&params);
+        let vector = to_vector(&params);
+        let directory = std::env::temp_dir();
+        let text = directory.join(format!("rusty-tune-{}.txt", std::process::id()));
+        let binary = directory.join(format!("rusty-tune-{}.bin", std::process::id()));
+        std::fs::write(
+            &text,
+            format!("{} | 20 | 1.0\n\n{} 0.5\nnonsense\n", FEN, FEN),
+        )
+        .unwrap();
+        let sample = DataSample {
+            position: Position::from_fen(FEN).unwrap(),
+            score: 20,
+            result: 0.0,
+        };
+        std::fs::write(&binary, [encode(&sample), encode(&sample)].concat()).unwrap();
+        let from_text = load(text.to_str().unwrap(), &layout, &params, &vector);
+        let from_binary = load(binary.to_str().unwrap(), &layout, &params, &vector);
+        std::fs::remove_file(&text).ok();
+        std::fs::remove_file(&binary).ok();
+        let results: Vec<f64> = from_text.unwrap().iter().map(|s| s.result).collect();
+        assert_eq!(results, [1.0, 0.5]);
+        let from_binary = from_binary.unwrap();
+        assert_eq!(from_binary.len(), 2);
+        assert_eq!(from_binary[0].result, 0.0);
+        assert!(!from_binary[0].features.is_empty());
+    }
+
+    #[test]
+    fn find_k_recovers_the_scaling() {
+        // results that follow the sigmoid exactly, for an evaluation held in one weight
+        let k = 1.3;
+        let samples: Vec<Sample> = (-20..=20)
+            .map(|i| {
+                let eval = i as f64 * 25.0;
+                Sample {
+                    features: vec![(0, eval as f32)],
+                    result: sigmoid(k, eval),
+                }
+            })
+            .collect();
+        assert!((find_k(&samples, &[1.0]) - k).abs() < 1e-3);
+    }
+
+    #[test]
+    fn adam_step_lowers_the_error() {
+        let params = EvalParams::default();
+        let layout = Layout::new(&params);
+        let mut vector = to_vector(&params);
+        // white to move a knight up and winning, a level opening drawn
+        let samples = [
+            (
+                "r1bqkb1r/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 5",
+                1.0,
+            ),
+            (FEN, 0.5),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/R1BQKBNR w KQkq - 0 1",
+                0.0,
+            ),
+        ]
+        .map(|(fen, result)| Sample::new(&parse_fen(fen).unwrap(), &layout, &params, result));
+        let k = 1.0;
+        let before = error(&samples, &vector, k);
+        let g = gradient(&samples, &vector, k);
+        Adam::new(vector.len()).step(&mut vector, &g);
+        assert!(error(&samples, &vector, k) < before);
+    }
+}
@@ -1,11 +1,18 @@
-use crate::engine::{best_move, best_move_with_time, evaluation_middlegame, Searcher};
-use chess::{Board, ChessMove};
+use rusty::chess::{BoardStatus, ChessMove, Color};
+use rusty::tools::{eval_table, print_divide, print_perft};
+use rusty::{
+    Engine, EngineConfig, EvalParams, IterationInfo, Network, Position, SearchLimits,
+    SearchObserver,
+};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 // allow for uci communication
 
 struct Uci {
-    board: Board,
     time: u64,
     inc: u64,
     movestogo: u64,
@@ -14,18 +21,34 @@ struct Uci {
     mate: u64,
     movetime: u64,
     infinite: bool,
-    searching: bool,
 }
 
 struct Listener {
     uci: Uci,
+    // shared with the search thread; see engine()
+    engine: Arc<Mutex<Engine>>,
+    stop: Arc<AtomicBool>,
+    // the running search, which prints its own bestmove
+    search: Option<JoinHandle<()>>,
+    // the network loaded with NNUEFile, handed to the engine while UseNNUE is on
+    network: Option<Arc<Network>>,
+    use_nnue: bool,
+    // MateChecksOnly, for go mate
+    mate_checks_only: bool,
 }
 
 impl Listener {
-    fn new() -> Listener {
+    fn new(config: EngineConfig) -> Listener {
+        let network = config.network.clone();
+        let engine = Engine::new(config);
         Listener {
+            use_nnue: network.is_some(),
+            mate_checks_only: false,
+            network,
+            stop: engine.stop_flag(),
+            engine: Arc::new(Mutex::new(engine)),
+            search: None,
             uci: Uci {
-                board: Board::default(),
                 time: 0,
                 inc: 0,
                 movestogo: 0,
@@ -34,69 +57,121 @@ impl Listener {
                 mate: 0,
                 movetime: 0,
                 infinite: false,
-                searching: false,
             },
         }
     }
 
-    fn handle(&mut self, line: &str) {
+    // false once the GUI has asked to quit
+    fn handle(&mut self, line: &str) -> bool {
         let mut args = line.split_whitespace();
         let command = args.next().unwrap_or("");
         match command {
             "uci" => self.uci(),
             "isready" => self.isready(),
+            "setoption" => self.setoption(line),
             "ucinewgame" => self.ucinewgame(),
             "position" => self.position(args),
             "go" => self.go(args),
+            "perft" => self.perft(args, false),
+            "divide" => self.perft(args, true),
+            "eval" => self.eval(),
             "stop" => self.stop(),
-            "quit" => self.quit(),
+            "quit" => {
+                self.stop();
+                return false;
+            }
             _ => (),
         }
+        true
     }
 
     fn uci(&mut self) {
         println!("id name Rusty");
         println!("id author Rusty");
+        println!("option name EvalFile type string default <empty>");
+        println!("option name NNUEFile type string default <empty>");
+        println!("option name UseNNUE type check default false");
+        println!("option name MateChecksOnly type check default false");
         println!("uciok");
     }
 
+    fn setoption(&mut self, line: &str) {
+        // option values may contain spaces, so split on the keywords rather than on whitespace
+        let Some((name, value)) = line
+            .trim()
+            .strip_prefix("setoption name ")
+            .map(|rest| rest.split_once(" value ").unwrap_or((rest, "")))
+        else {
+            return;
+        };
+        let name = name.trim();
+        let value = value.trim();
+        let empty = value.is_empty() || value == "<empty>";
+        if name.eq_ignore_ascii_case("EvalFile") {
+            if empty {
+                self.engine().set_params(EvalParams::default());
+                return;
+            }
+            match EvalParams::load(value) {
+                Ok((params, warnings)) => {
+                    for warning in warnings {
+                        println!("info string warning: {}", warning);
+                    }
+                    self.engine().set_params(params);
+                }
+                Err(error) => println!("info string error: {}", error),
+            }
+        } else if name.eq_ignore_ascii_case("NNUEFile") {
+            // UseNNUE decides whether the network is used
+            if empty {
+                self.network = None;
+            } else {
+                match Network::load(value) {
+                    Ok(network) => self.network = Some(Arc::new(network)),
+                    Err(error) => println!("info string error: {}", error),
+                }
+            }
+        } else if name.eq_ignore_ascii_case("MateChecksOnly") {
+            self.mate_checks_only = value.eq_ignore_ascii_case("true");
+            return;
+        } else if name.eq_ignore_ascii_case("UseNNUE") {
+            self.use_nnue = value.eq_ignore_ascii_case("true");
+            if self.use_nnue && self.network.is_none() {
+                println!("info string error: no network loaded, set NNUEFile first");
+            }
+        }
+        let network = self.network.clone().filter(|_| self.use_nnue);
+        self.engine().set_network(network);
+    }
+
     fn isready(&mut self) {
         println!("readyok");
     }
 
     fn ucinewgame(&mut self) {
-        self.uci.board = Board::default();
+        self.engine().set_position(Position::default());
     }
 
-    fn position(&mut self, mut args: std::str::SplitWhitespace) {
-        let mut fen = String::new();
-        let mut moves = Vec::new();
-        let mut next = args.next().unwrap_or("");
-        while next != "" {
-            if next == "startpos" {
-                fen = String::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
-            } else if next == "fen" {
-                while next != "moves" {
-                    next = args.next().unwrap_or("");
-                    if next != "moves" {
-                        fen.push_str(next);
-                        fen.push_str(" ");
-                    }
-                }
-            } else if next == "moves" {
-                while let Some(m) = args.next() {
-                    moves.push(ChessMove::from_str(m).unwrap());
-                }
-            }
-            next = args.next().unwrap_or("");
+    fn position(&mut self, args: std::str::SplitWhitespace) {
+        // the previous position is kept if anything in the command is malformed
+        match parse_position(args) {
+            Ok(position) => self.engine().set_position(position),
+            Err(error) => println!("info string error: {}", error),
         }
-        self.uci.board = Board::from_str(&fen).unwrap();
-        for m in moves {
-            self.uci.board = self.uci.board.make_move_new(m);
+    }
+
+    fn go(&mut self, args: std::str::SplitWhitespace) {
+        let mut peek = args.clone();
+        if peek.next() == Some("perft") {
+            self.perft(peek, true);
+            return;
         }
+        self.read_go(args);
+        self.search();
     }
 
-    fn go(&mut self, mut args: std::str::SplitWhitespace) {
+    // fills in the limits of a go command
+    fn read_go(&mut self, mut args: std::str::SplitWhitespace) {
         self.uci.time = 0;
         self.uci.inc = 0;
         self.uci.movestogo = 0;
@@ -105,82 +180,346 @@ impl Listener {
         self.uci.mate = 0;
         self.uci.movetime = 0;
         self.uci.infinite = false;
-        self.uci.searching = true;
         let mut next = args.next().unwrap_or("");
-        while next != "" {
-            if next == "wtime" {
-                self.uci.time = args.next().unwrap_or("0").parse().unwrap_or(0);
-            } else if next == "btime" {
-                self.uci.time = args.next().unwrap_or("0").parse().unwrap_or(0);
-            } else if next == "winc" {
-                self.uci.inc = args.next().unwrap_or("0").parse().unwrap_or(0);
-            } else if next == "binc" {
-                self.uci.inc = args.next().unwrap_or("0").parse().unwrap_or(0);
-            } else if next == "movestogo" {
-                self.uci.movestogo = args.next().unwrap_or("0").parse().unwrap_or(0);
-            } else if next == "depth" {
-                self.uci.depth = args.next().unwrap_or("0").parse().unwrap_or(0);
-            } else if next == "nodes" {
-                self.uci.nodes = args.next().unwrap_or("0").parse().unwrap_or(0);
-            } else if next == "mate" {
-                self.uci.mate = args.next().unwrap_or("0").parse().unwrap_or(0);
-            } else if next == "movetime" {
-                self.uci.movetime = args.next().unwrap_or("0").parse().unwrap_or(0);
-            } else if next == "infinite" {
-                self.uci.infinite = true;
+        // only the clock of the side to move matters
+        let (time, inc) = match self.engine().position().board.side_to_move() {
+            Color::White => ("wtime", "winc"),
+            Color::Black => ("btime", "binc"),
+        };
+        while !next.is_empty() {
+            let mut value = || args.next().unwrap_or("0").parse().unwrap_or(0);
+            match next {
+                n if n == time => self.uci.time = value(),
+                n if n == inc => self.uci.inc = value(),
+                "movestogo" => self.uci.movestogo = value(),
+                "depth" => self.uci.depth = value(),
+                "nodes" => self.uci.nodes = value(),
+                "mate" => self.uci.mate = value(),
+                "movetime" => self.uci.movetime = value(),
+                "infinite" => self.uci.infinite = true,
+                _ => {}
             }
             next = args.next().unwrap_or("");
         }
-        if self.uci.infinite {
-            self.search();
-        } else if self.uci.movetime > 0 {
-            self.search();
-        } else if self.uci.depth > 0 {
-            self.search();
-        } else if self.uci.nodes > 0 {
-            self.search();
-        } else if self.uci.time > 0 {
-            self.search();
-        } else {
-            self.search();
-        }
-    }
-
-    // fn search(&mut self) {
-    //     if self.uci.movetime > 0 {
-    //         let (best_move, score) = best_move_with_time(&self.uci.board, self.uci.movetime / 100);
-    //         println!("bestmove {}", best_move);
-    //         return;
-    //     }
-    //     if self.uci.depth > 0 {
-    //         let (best_move, score) = best_move(&self.uci.board, self.uci.depth as u8);
-    //         println!("bestmove {}", best_move);
-    //         return;
-    //     }
-    //     let (best_move, score) = best_move(&self.uci.board, 4);
-    //     println!("bestmove {}", best_move);
-    // }
+    }
+
+    fn limits(&self) -> SearchLimits {
+        SearchLimits {
+            // the library caps the mate length further, to what a score can report
+            mate: Some(u8::try_from(self.uci.mate).unwrap_or(u8::MAX)).filter(|&m| m > 0),
+            mate_checks_only: self.mate_checks_only,
+            infinite: self.uci.infinite,
+            depth: Some(u8::try_from(self.uci.depth).unwrap_or(u8::MAX)).filter(|&d| d > 0),
+            nodes: Some(self.uci.nodes).filter(|&n| n > 0),
+            movetime: Some(self.uci.movetime)
+                .filter(|&t| t > 0)
+                .or_else(|| clock_time(self.uci.time, self.uci.inc, self.uci.movestogo))
+                .map(Duration::from_millis),
+        }
+    }
 
+    // searches on another thread, so that stop can still be read
     fn search(&mut self) {
-        let (best_move, score) = best_move_with_time(&self.uci.board, 1000);
-        println!("bestmove {}", best_move);
+        let limits = self.limits();
+        self.wait();
+        let engine = self.engine.clone();
+        self.search = Some(thread::spawn(move || {
+            let mut printer = InfoPrinter {
+                start: Instant::now(),
+            };
+            let result = engine.lock().unwrap().search_with(limits, &mut printer);
+            match result.best_move {
+                Some(m) => println!("bestmove {}", m),
+                None => println!("bestmove 0000"),
+            }
+        }));
+    }
+
+    // lets the running search finish
+    fn wait(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.join().expect("the search thread panicked");
+        }
+    }
+
+    // the engine, after stopping the running search: a command that needs the engine while
+    // it searches, such as a go or position without a stop first, would otherwise wait for
+    // a go infinite forever
+    fn engine(&mut self) -> MutexGuard<'_, Engine> {
+        self.stop();
+        self.engine.lock().unwrap()
+    }
+
+    fn perft(&mut self, mut args: std::str::SplitWhitespace, divide: bool) {
+        match args.next().map(|d| d.parse::<u8>()) {
+            Some(Ok(depth)) if divide => print_divide(&self.engine().position().board, depth),
+            Some(Ok(depth)) => print_perft(&self.engine().position().board, depth),
+            _ => println!("info string error: perft needs a depth"),
+        }
     }
 
+    fn eval(&mut self) {
+        let engine = self.engine();
+        let board = &engine.position().board;
+        for line in eval_table(board, engine.params()) {
+            println!("{}", line);
+        }
+        if let Some(network) = engine.network() {
+            if board.status() == BoardStatus::Ongoing {
+                let eval = network.evaluate_board(board);
+                println!("NNUE evaluation: {:+.2} (white side)", eval as f64 / 100.0);
+            }
+        }
+    }
+
+    // ends the running search, which answers with the best move of its last iteration
     fn stop(&mut self) {
-        self.uci.searching = false;
+        if self.search.is_some() {
+            self.stop.store(true, Ordering::Relaxed);
+            self.wait();
+            // the search may have ended on its own just before the flag was set
+            self.stop.store(false, Ordering::Relaxed);
+        }
     }
+}
 
-    fn quit(&mut self) {
-        self.uci.searching = false;
+// milliseconds for one move out of the remaining `time`, spread over `movestogo` moves or 30
+fn clock_time(time: u64, inc: u64, movestogo: u64) -> Option<u64> {
+    if time == 0 {
+        return None;
     }
+    let moves = if movestogo > 0 { movestogo } else { 30 };
+    // keep a little in reserve for the GUI's overhead
+    Some((time / moves + inc).min(time.saturating_sub(50)).max(1))
+}
 
+fn parse_position(mut args: std::str::SplitWhitespace) -> Result<Position, String> {
+    let mut position = match args.next() {
+        Some("startpos") => {
+            match args.next() {
+                None | Some("moves") => {}
+                Some(token) => return Err(format!("unexpected token '{}' after startpos", token)),
+            }
+            Position::default()
+        }
+        Some("fen") => {
+            let mut fen = Vec::new();
+            for token in args.by_ref() {
+                if token == "moves" {
+                    break;
+                }
+                fen.push(token);
+            }
+            Position::from_fen(&fen.join(" ")).map_err(|e| format!("invalid fen: {}", e))?
+        }
+        Some(token) => return Err(format!("expected startpos or fen, found '{}'", token)),
+        None => return Err(String::from("missing startpos or fen")),
+    };
+    for token in args {
+        let m = ChessMove::from_str(token).map_err(|_| format!("invalid move '{}'", token))?;
+        if !position.board.legal(m) {
+            return Err(format!("illegal move '{}'", token));
+        }
+        position = position.make_move_new(m);
+    }
+    Ok(position)
 }
 
-pub(crate) fn main() {
-    let mut listener = Listener::new();
+// prints the search progress as info lines
+struct InfoPrinter {
+    start: Instant,
+}
+
+impl SearchObserver for InfoPrinter {
+    fn iteration(&mut self, info: &IterationInfo) {
+        let pv: Vec<String> = info.pv.iter().map(|m| m.to_string()).collect();
+        let millis = info.elapsed.as_millis() as u64;
+        println!(
+            "info depth {} seldepth {} score {} nodes {} time {} nps {} pv {}",
+            info.depth,
+            info.seldepth,
+            info.score.to_uci(),
+            info.nodes,
+            millis,
+            info.nodes * 1000 / millis.max(1),
+            pv.join(" ")
+        );
+    }
+
+    // only once the search has been running for a while, to keep short searches quiet
+    fn current_move(&mut self, m: ChessMove, number: usize, depth: u8) {
+        if self.start.elapsed() >= Duration::from_secs(1) {
+            println!(
+                "info depth {} currmove {} currmovenumber {}",
+                depth, m, number
+            );
+        }
+    }
+}
+
+// `first` is the line the protocol was chosen by
+pub(crate) fn main(config: EngineConfig, first: &str) {
+    let mut listener = Listener::new(config);
+    if !listener.handle(first) {
+        return;
+    }
     let mut line = String::new();
-    while let Ok(_) = std::io::stdin().read_line(&mut line) {
-        listener.handle(&line);
+    while matches!(std::io::stdin().read_line(&mut line), Ok(n) if n > 0) {
+        if !listener.handle(&line) {
+            return;
+        }
         line.clear();
     }
-}
\ No newline at end of file
+    // the input has ended: a search with limits still gets to answer
+    if listener.uci.infinite {
+        listener.stop();
+    }
+    listener.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listener() -> Listener {
+        Listener::new(EngineConfig::default())
+    }
+
+    fn go(listener: &mut Listener, line: &str) -> SearchLimits {
+        listener.read_go(line.split_whitespace());
+        listener.limits()
+    }
+
+    fn position(command: &str) -> Result<Position, String> {
+        parse_position(command.split_whitespace())
+    }
+
+    #[test]
+    fn parses_positions_with_moves() {
+        let played = position("startpos moves e2e4 e7e5 g1f3").unwrap();
+        assert_eq!(
+            played.to_fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+        let fen = "4k3/8/8/8/8/8/8/4K2R w K - 0 1";
+        assert_eq!(position(&format!("fen {}", fen)).unwrap().to_fen(), fen);
+        assert!(position(&format!("fen {} moves e1g1", fen)).is_ok());
+    }
+
+    #[test]
+    fn rejects_malformed_positions() {
+        assert_eq!(
+            position("startpos moves e2e4 e2e4"),
+            Err(String::from("illegal move 'e2e4'"))
+        );
+        assert_eq!(
+            position("startpos moves e2e4 castle"),
+            Err(String::from("invalid move 'castle'"))
+        );
+        assert_eq!(
+            position("fen moves e2e4"),
+            Err(String::from(
+                "invalid fen: expected 4 to 6 FEN fields, found 0"
+            ))
+        );
+        assert!(position("fen").is_err());
+        assert!(position("fen 4k3/8/8/8/8/8/8/4K3 w - - 0 1 5").is_err());
+        assert!(position("startpos e2e4").is_err());
+        assert!(position("").is_err());
+    }
+
+    #[test]
+    fn keeps_the_position_after_an_error() {
+        let mut listener = listener();
+        listener.handle("position startpos moves e2e4");
+        let before = *listener.engine().position();
+        listener.handle("position startpos moves e2e4 e7e5 e1e3");
+        assert_eq!(*listener.engine().position(), before);
+        listener.handle("position fen 8/8/8 w - - 0 1");
+        assert_eq!(*listener.engine().position(), before);
+        listener.handle("position fen");
+        assert_eq!(*listener.engine().position(), before);
+    }
+
+    #[test]
+    fn stop_ends_an_infinite_search() {
+        let mut listener = listener();
+        // a stop with nothing to stop must not cut the next search short
+        listener.handle("stop");
+        assert!(!listener.stop.load(Ordering::Relaxed));
+        listener.handle("position startpos");
+        listener.handle("go infinite");
+        assert!(listener.limits().infinite);
+        thread::sleep(Duration::from_millis(200));
+        assert!(!listener.search.as_ref().unwrap().is_finished());
+        listener.handle("stop");
+        assert!(listener.search.is_none());
+        assert!(!listener.stop.load(Ordering::Relaxed));
+        // the engine is free again
+        listener.handle("position startpos moves e2e4");
+        assert_eq!(
+            listener.engine().position().board.side_to_move(),
+            Color::Black
+        );
+    }
+
+    #[test]
+    fn commands_during_a_search_stop_it() {
+        let mut listener = listener();
+        listener.handle("position startpos");
+        listener.handle("go infinite");
+        // a GUI that forgets to send stop
+        listener.handle("go depth 1");
+        listener.wait();
+        listener.handle("go infinite");
+        listener.handle("position startpos moves e2e4");
+        assert!(listener.search.is_none());
+        assert_eq!(
+            listener.engine().position().board.side_to_move(),
+            Color::Black
+        );
+        listener.handle("go infinite");
+        listener.handle("setoption name UseNNUE value false");
+        assert!(listener.search.is_none());
+        assert!(!listener.stop.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn uses_the_clock_of_the_side_to_move() {
+        let mut listener = listener();
+        listener.handle("position startpos moves e2e4");
+        let millis = |ms| Some(Duration::from_millis(ms));
+        // black's 60 seconds over 30 moves, plus black's increment
+        let limits = go(&mut listener, "wtime 1000 btime 60000 winc 0 binc 1000");
+        assert_eq!(limits.movetime, millis(3000));
+        assert_eq!(
+            go(&mut listener, "btime 10000 movestogo 5").movetime,
+            millis(2000)
+        );
+        assert_eq!(
+            go(&mut listener, "btime 10000 movetime 500").movetime,
+            millis(500)
+        );
+        // the increment is not spent before it arrives
+        assert_eq!(go(&mut listener, "btime 40 binc 1000").movetime, millis(1));
+        assert_eq!(go(&mut listener, "wtime 60000").movetime, None);
+    }
+
+    #[test]
+    fn long_mates_do_not_wrap_around() {
+        let mut listener = listener();
+        assert_eq!(go(&mut listener, "mate 3").mate, Some(3));
+        assert_eq!(go(&mut listener, "mate 300").mate, Some(u8::MAX));
+        assert_eq!(go(&mut listener, "").mate, None);
+    }
+
+    #[test]
+    fn mate_checks_only_is_an_option() {
+        let mut listener = listener();
+        assert!(!go(&mut listener, "mate 3").mate_checks_only);
+        listener.handle("setoption name MateChecksOnly value true");
+        assert!(go(&mut listener, "mate 3").mate_checks_only);
+        listener.handle("setoption name MateChecksOnly value false");
+        assert!(!go(&mut listener, "mate 3").mate_checks_only);
+    }
+}
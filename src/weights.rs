@@ -1,137 +1,995 @@
-// hard coded positional weights for the pieces
-
-// middlegame weights
-
-pub const PAWN_MG_WT: [[i32; 8]; 8] = [
-    [0, 0, 0, 0, 0, 0, 0, 0],
-    [5, 10, 10, -20, -20, 10, 10, 5],
-    [5, -5, -10, 0, 0, -10, -5, 5],
-    [0, 0, 0, 20, 20, 0, 0, 0],
-    [5, 5, 10, 25, 25, 10, 5, 5],
-    [10, 10, 20, 30, 30, 20, 10, 10],
-    [50, 50, 50, 50, 50, 50, 50, 50],
-    [0, 0, 0, 0, 0, 0, 0, 0],
-];
+use chess::Piece;
+use std::fmt::Write as _;
+use std::fs;
 
-pub const KNIGHT_MG_WT: [[i32; 8]; 8] = [
-    [-50, -40, -30, -30, -30, -30, -40, -50],
-    [-40, -20, 0, 0, 0, 0, -20, -40],
-    [-30, 0, 10, 15, 15, 10, 0, -30],
-    [-30, 5, 15, 20, 20, 15, 5, -30],
-    [-30, 0, 15, 20, 20, 15, 0, -30],
-    [-30, 5, 10, 15, 15, 10, 5, -30],
-    [-40, -20, 0, 5, 5, 0, -20, -40],
-    [-50, -40, -30, -30, -30, -30, -40, -50],
-];
+use crate::king::MAX_ATTACK_UNITS;
 
-pub const BISHOP_MG_WT: [[i32; 8]; 8] = [
-    [-20, -10, -10, -10, -10, -10, -10, -20],
-    [-10, 0, 0, 0, 0, 0, 0, -10],
-    [-10, 0, 5, 10, 10, 5, 0, -10],
-    [-10, 5, 5, 10, 10, 5, 5, -10],
-    [-10, 0, 10, 10, 10, 10, 0, -10],
-    [-10, 10, 10, 10, 10, 10, 10, -10],
-    [-10, 5, 0, 0, 0, 0, 5, -10],
-    [-20, -10, -10, -10, -10, -10, -10, -20],
-];
+// Evaluation weights. The compiled-in defaults can be replaced at runtime by a weights file
+// of `name = [values]` lines: a middlegame and an endgame value for each weight, or the 64
+// entries of a piece-square table. A plain `name = value` gives every weight of the name that
+// value in both phases, as in the older files without phases.
 
-pub const ROOK_MG_WT: [[i32; 8]; 8] = [
-    [0, 0, 0, 0, 0, 0, 0, 0],
-    [5, 10, 10, 10, 10, 10, 10, 5],
-    [-5, 0, 0, 0, 0, 0, 0, -5],
-    [-5, 0, 0, 0, 0, 0, 0, -5],
-    [-5, 0, 0, 0, 0, 0, 0, -5],
-    [-5, 0, 0, 0, 0, 0, 0, -5],
-    [-5, 0, 0, 0, 0, 0, 0, -5],
-    [0, 0, 0, 5, 5, 0, 0, 0],
-];
+// middlegame and endgame value of a weight, blended by game phase
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Weight {
+    pub mg: i32,
+    pub eg: i32,
+}
 
-pub const QUEEN_MG_WT: [[i32; 8]; 8] = [
-    [-20, -10, -10, -5, -5, -10, -10, -20],
-    [-10, 0, 0, 0, 0, 0, 0, -10],
-    [-10, 0, 5, 5, 5, 5, 0, -10],
-    [-5, 0, 5, 5, 5, 5, 0, -5],
-    [0, 0, 5, 5, 5, 5, 0, -5],
-    [-10, 5, 5, 5, 5, 5, 0, -10],
-    [-10, 0, 5, 0, 0, 0, 0, -10],
-    [-20, -10, -10, -5, -5, -10, -10, -20],
-];
+const fn w(mg: i32, eg: i32) -> Weight {
+    Weight { mg, eg }
+}
 
-pub const KING_MG_WT: [[i32; 8]; 8] = [
-    [-30, -40, -40, -50, -50, -40, -40, -30],
-    [-30, -40, -40, -50, -50, -40, -40, -30],
-    [-30, -40, -40, -50, -50, -40, -40, -30],
-    [-30, -40, -40, -50, -50, -40, -40, -30],
-    [-20, -30, -30, -40, -40, -30, -30, -20],
-    [-10, -20, -20, -20, -20, -20, -20, -10],
-    [20, 20, 0, 0, 0, 0, 20, 20],
-    [20, 30, 10, 0, 0, 10, 30, 20],
-];
+// piece-square tables are seen from white's side with the eighth rank in the first row;
+// black pieces look them up mirrored
+pub type Table = [[i32; 8]; 8];
 
-// endgame weights
-
-pub const PAWN_EG_WT: [[i32; 8]; 8] = [
-    [0, 0, 0, 0, 0, 0, 0, 0],
-    [50, 50, 50, 50, 50, 50, 50, 50],
-    [10, 10, 20, 30, 30, 20, 10, 10],
-    [5, 5, 10, 25, 25, 10, 5, 5],
-    [0, 0, 0, 20, 20, 0, 0, 0],
-    [5, -5, -10, 0, 0, -10, -5, 5],
-    [5, 10, 10, -20, -20, 10, 10, 5],
-    [0, 0, 0, 0, 0, 0, 0, 0],
-];
+// everything the evaluation can add to the score
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Term {
+    Material(Piece),
+    // index into the piece's tables (row * 8 + column)
+    PieceSquare(Piece, usize),
+    BishopPair,
+    RookOnOpenFile,
+    RookOnSemiOpenFile,
+    RookOn7th,
+    RookOn8th,
+    IsolatedPawn,
+    DoubledPawn,
+    BackwardPawn,
+    // by rank counted from the pawn's own side
+    PassedPawn(usize),
+    CandidatePassedPawn(usize),
+    ConnectedPawn(usize),
+    PhalanxPawn(usize),
+    CenterControl,
+    // by attack units against the king
+    KingDanger(usize),
+    // own pawns one and two ranks in front of the king
+    PawnShield(usize),
+    // enemy pawns one to four ranks in front of the king
+    PawnStorm(usize),
+    KingOpenFile,
+    KingSemiOpenFile,
+    // by the number of safe squares the piece can move to
+    Mobility(Piece, usize),
+    // by the piece attacked
+    ThreatByLesser(Piece),
+    Hanging,
+    PawnPushThreat,
+    Restricted,
+    // by knight or bishop
+    Outpost(Piece),
+    BadBishop,
+    KnightTropism,
+    TrappedBishop,
+    TrappedRook,
+    CastlingBoth,
+    CastlingOne,
+}
 
-pub const KNIGHT_EG_WT: [[i32; 8]; 8] = [
-    [-50, -40, -30, -30, -30, -30, -40, -50],
-    [-40, -20, 0, 5, 5, 0, -20, -40],
-    [-30, 5, 10, 15, 15, 10, 5, -30],
-    [-30, 0, 15, 20, 20, 15, 0, -30],
-    [-30, 5, 15, 20, 20, 15, 5, -30],
-    [-30, 0, 10, 15, 15, 10, 0, -30],
-    [-40, -20, 0, 0, 0, 0, -20, -40],
-    [-50, -40, -30, -30, -30, -30, -40, -50],
-];
+impl Term {
+    // heading the term is listed under by the eval command
+    pub(crate) fn category(&self) -> &'static str {
+        match self {
+            Term::Material(_) => "Material",
+            Term::PieceSquare(..) => "Piece-square tables",
+            Term::BishopPair | Term::Outpost(_) | Term::BadBishop | Term::KnightTropism => {
+                "Minor pieces"
+            }
+            Term::TrappedBishop | Term::TrappedRook => "Trapped pieces",
+            Term::RookOnOpenFile | Term::RookOnSemiOpenFile => "Rook files",
+            Term::RookOn7th | Term::RookOn8th => "Rook ranks",
+            Term::IsolatedPawn
+            | Term::DoubledPawn
+            | Term::BackwardPawn
+            | Term::ConnectedPawn(_)
+            | Term::PhalanxPawn(_) => "Pawn structure",
+            Term::PassedPawn(_) | Term::CandidatePassedPawn(_) => "Passed pawns",
+            Term::CenterControl => "Center control",
+            Term::KingDanger(_)
+            | Term::PawnShield(_)
+            | Term::PawnStorm(_)
+            | Term::KingOpenFile
+            | Term::KingSemiOpenFile => "King safety",
+            Term::Mobility(..) => "Mobility",
+            Term::ThreatByLesser(_) | Term::Hanging | Term::PawnPushThreat | Term::Restricted => {
+                "Threats"
+            }
+            Term::CastlingBoth | Term::CastlingOne => "Castling",
+        }
+    }
 
-pub const BISHOP_EG_WT: [[i32; 8]; 8] = [
-    [-20, -10, -10, -10, -10, -10, -10, -20],
-    [-10, 5, 0, 0, 0, 0, 5, -10],
-    [-10, 10, 10, 10, 10, 10, 10, -10],
-    [-10, 0, 10, 10, 10, 10, 0, -10],
-    [-10, 5, 5, 10, 10, 5, 5, -10],
-    [-10, 0, 5, 10, 10, 5, 0, -10],
-    [-10, 0, 0, 0, 0, 0, 0, -10],
-    [-20, -10, -10, -10, -10, -10, -10, -20],
-];
+    // where the middlegame and endgame value of the term sit in the weights file:
+    // entry name and offset into its list of values
+    pub(crate) fn locations(&self) -> [(&'static str, usize); 2] {
+        match self {
+            Term::PieceSquare(piece, i) => {
+                let names = PIECE_NAMES[piece.to_index()];
+                [(names.1, *i), (names.2, *i)]
+            }
+            Term::Material(piece) => [
+                (PIECE_NAMES[piece.to_index()].0, 0),
+                (PIECE_NAMES[piece.to_index()].0, 1),
+            ],
+            Term::Outpost(piece) => {
+                let name = if *piece == Piece::Knight {
+                    "knight_outpost"
+                } else {
+                    "bishop_outpost"
+                };
+                [(name, 0), (name, 1)]
+            }
+            Term::ThreatByLesser(piece) => [
+                ("threat_by_lesser", 2 * piece.to_index()),
+                ("threat_by_lesser", 2 * piece.to_index() + 1),
+            ],
+            Term::PassedPawn(i)
+            | Term::CandidatePassedPawn(i)
+            | Term::ConnectedPawn(i)
+            | Term::PhalanxPawn(i)
+            | Term::KingDanger(i)
+            | Term::PawnShield(i)
+            | Term::PawnStorm(i)
+            | Term::Mobility(_, i) => {
+                let name = match self {
+                    Term::PassedPawn(_) => "passed_pawn",
+                    Term::CandidatePassedPawn(_) => "candidate_passed_pawn",
+                    Term::ConnectedPawn(_) => "connected_pawn",
+                    Term::PhalanxPawn(_) => "phalanx_pawn",
+                    Term::KingDanger(_) => "king_danger",
+                    Term::PawnShield(_) => "pawn_shield",
+                    Term::PawnStorm(_) => "pawn_storm",
+                    Term::Mobility(Piece::Knight, _) => "knight_mobility",
+                    Term::Mobility(Piece::Bishop, _) => "bishop_mobility",
+                    Term::Mobility(Piece::Rook, _) => "rook_mobility",
+                    _ => "queen_mobility",
+                };
+                [(name, 2 * i), (name, 2 * i + 1)]
+            }
+            _ => {
+                let name = match self {
+                    Term::BishopPair => "bishop_pair",
+                    Term::RookOnOpenFile => "rook_on_open_file",
+                    Term::RookOnSemiOpenFile => "rook_on_semi_open_file",
+                    Term::RookOn7th => "rook_on_7th",
+                    Term::RookOn8th => "rook_on_8th",
+                    Term::IsolatedPawn => "isolated_pawn",
+                    Term::DoubledPawn => "doubled_pawn",
+                    Term::BackwardPawn => "backward_pawn",
+                    Term::CenterControl => "center_control",
+                    Term::KingOpenFile => "king_open_file",
+                    Term::KingSemiOpenFile => "king_semi_open_file",
+                    Term::Hanging => "hanging",
+                    Term::PawnPushThreat => "pawn_push_threat",
+                    Term::Restricted => "restricted",
+                    Term::BadBishop => "bad_bishop",
+                    Term::KnightTropism => "knight_tropism",
+                    Term::TrappedBishop => "trapped_bishop",
+                    Term::TrappedRook => "trapped_rook",
+                    Term::CastlingBoth => "castling_both",
+                    _ => "castling_one",
+                };
+                [(name, 0), (name, 1)]
+            }
+        }
+    }
+}
 
-pub const ROOK_EG_WT: [[i32; 8]; 8] = [
-    [0, 0, 0, 5, 5, 0, 0, 0],
-    [-5, 0, 0, 0, 0, 0, 0, -5],
-    [-5, 0, 0, 0, 0, 0, 0, -5],
-    [-5, 0, 0, 0, 0, 0, 0, -5],
-    [-5, 0, 0, 0, 0, 0, 0, -5],
-    [-5, 0, 0, 0, 0, 0, 0, -5],
-    [5, 10, 10, 10, 10, 10, 10, 5],
-    [0, 0, 0, 0, 0, 0, 0, 0],
+// material weight and piece-square table names by piece
+const PIECE_NAMES: [(&str, &str, &str); 6] = [
+    ("pawn", "pawn_mg", "pawn_eg"),
+    ("knight", "knight_mg", "knight_eg"),
+    ("bishop", "bishop_mg", "bishop_eg"),
+    ("rook", "rook_mg", "rook_eg"),
+    ("queen", "queen_mg", "queen_eg"),
+    ("king", "king_mg", "king_eg"),
 ];
 
-pub const QUEEN_EG_WT: [[i32; 8]; 8] = [
-    [-20, -10, -10, -5, -5, -10, -10, -20],
-    [-10, 0, 0, 0, 0, 0, 0, -10],
-    [-10, 0, 5, 5, 5, 5, 0, -10],
-    [-5, 0, 5, 5, 5, 5, 0, -5],
-    [0, 0, 5, 5, 5, 5, 0, -5],
-    [-10, 5, 5, 5, 5, 5, 0, -10],
-    [-10, 0, 5, 0, 0, 0, 0, -10],
-    [-20, -10, -10, -5, -5, -10, -10, -20],
+// weights the evaluation no longer has; files that still set them load with a warning. Kings
+// have no material weight, as both sides always have one.
+const RETIRED: [&str; 5] = [
+    "pawn_on_7th",
+    "king_safety",
+    "king",
+    "queen_on_7th",
+    "queen_on_8th",
 ];
 
-pub const KING_EG_WT: [[i32; 8]; 8] = [
-    [-50, -40, -30, -20, -20, -30, -40, -50],
-    [-30, -20, -10, 0, 0, -10, -20, -30],
-    [-30, -10, 20, 30, 30, 20, -10, -30],
-    [-30, -10, 30, 40, 40, 30, -10, -30],
-    [-30, -10, 30, 40, 40, 30, -10, -30],
-    [-30, -10, 20, 30, 30, 20, -10, -30],
-    [-30, -30, 0, 0, 0, 0, -30, -30],
-    [-50, -30, -30, -30, -30, -30, -30, -50],
-];
+// how an entry of the weights file is stored
+pub(crate) enum Entry<'a> {
+    Weights(&'a mut [Weight]),
+    Table(&'a mut Table, Phase),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Phase {
+    Middlegame,
+    Endgame,
+}
+
+impl Entry<'_> {
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Entry::Weights(weights) => weights.len() * 2,
+            Entry::Table(..) => 64,
+        }
+    }
+
+    pub(crate) fn phase(&self, offset: usize) -> Phase {
+        match self {
+            Entry::Weights(_) if offset.is_multiple_of(2) => Phase::Middlegame,
+            Entry::Weights(_) => Phase::Endgame,
+            Entry::Table(_, phase) => *phase,
+        }
+    }
+
+    pub(crate) fn get(&self, offset: usize) -> i32 {
+        match self {
+            Entry::Weights(weights) if offset.is_multiple_of(2) => weights[offset / 2].mg,
+            Entry::Weights(weights) => weights[offset / 2].eg,
+            Entry::Table(table, _) => table[offset / 8][offset % 8],
+        }
+    }
+
+    pub(crate) fn set(&mut self, offset: usize, value: i32) {
+        match self {
+            Entry::Weights(weights) if offset.is_multiple_of(2) => weights[offset / 2].mg = value,
+            Entry::Weights(weights) => weights[offset / 2].eg = value,
+            Entry::Table(table, _) => table[offset / 8][offset % 8] = value,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalParams {
+    pub pawn: Weight,
+    pub knight: Weight,
+    pub bishop: Weight,
+    pub rook: Weight,
+    pub queen: Weight,
+    pub bishop_pair: Weight,
+    pub rook_on_open_file: Weight,
+    pub rook_on_semi_open_file: Weight,
+    pub rook_on_7th: Weight,
+    pub rook_on_8th: Weight,
+    pub isolated_pawn: Weight,
+    pub doubled_pawn: Weight,
+    pub backward_pawn: Weight,
+    // by relative rank; the first and last entries are never used
+    pub passed_pawn: [Weight; 8],
+    pub candidate_passed_pawn: [Weight; 8],
+    pub connected_pawn: [Weight; 8],
+    pub phalanx_pawn: [Weight; 8],
+    pub center_control: Weight,
+    // by attack units against the king
+    pub king_danger: [Weight; MAX_ATTACK_UNITS],
+    pub pawn_shield: [Weight; 2],
+    pub pawn_storm: [Weight; 4],
+    pub king_open_file: Weight,
+    pub king_semi_open_file: Weight,
+    // by the number of safe squares
+    pub knight_mobility: [Weight; 9],
+    pub bishop_mobility: [Weight; 14],
+    pub rook_mobility: [Weight; 15],
+    pub queen_mobility: [Weight; 28],
+    // by the piece attacked; the pawn and king entries are never used
+    pub threat_by_lesser: [Weight; 6],
+    pub hanging: Weight,
+    pub pawn_push_threat: Weight,
+    pub restricted: Weight,
+    pub knight_outpost: Weight,
+    pub bishop_outpost: Weight,
+    // per own pawn on the bishop's colour
+    pub bad_bishop: Weight,
+    // per square closer than seven to the enemy king
+    pub knight_tropism: Weight,
+    pub trapped_bishop: Weight,
+    pub trapped_rook: Weight,
+    pub castling_both: Weight,
+    pub castling_one: Weight,
+    pub pawn_mg: Table,
+    pub pawn_eg: Table,
+    pub knight_mg: Table,
+    pub knight_eg: Table,
+    pub bishop_mg: Table,
+    pub bishop_eg: Table,
+    pub rook_mg: Table,
+    pub rook_eg: Table,
+    pub queen_mg: Table,
+    pub queen_eg: Table,
+    pub king_mg: Table,
+    pub king_eg: Table,
+}
+
+pub const DEFAULT_PARAMS: EvalParams = EvalParams {
+    pawn: w(100, 100),
+    knight: w(320, 320),
+    bishop: w(330, 330),
+    rook: w(500, 500),
+    queen: w(900, 900),
+    bishop_pair: w(50, 50),
+    rook_on_open_file: w(10, 10),
+    rook_on_semi_open_file: w(5, 5),
+    rook_on_7th: w(20, 20),
+    rook_on_8th: w(30, 30),
+    isolated_pawn: w(-10, -15),
+    doubled_pawn: w(-10, -20),
+    backward_pawn: w(-8, -10),
+    passed_pawn: [
+        w(0, 0),
+        w(5, 10),
+        w(10, 15),
+        w(15, 25),
+        w(30, 45),
+        w(50, 80),
+        w(80, 130),
+        w(0, 0),
+    ],
+    candidate_passed_pawn: [
+        w(0, 0),
+        w(3, 5),
+        w(5, 8),
+        w(8, 12),
+        w(12, 20),
+        w(20, 30),
+        w(0, 0),
+        w(0, 0),
+    ],
+    connected_pawn: [
+        w(0, 0),
+        w(0, 0),
+        w(5, 3),
+        w(8, 5),
+        w(12, 10),
+        w(20, 20),
+        w(30, 40),
+        w(0, 0),
+    ],
+    phalanx_pawn: [
+        w(0, 0),
+        w(2, 0),
+        w(3, 2),
+        w(5, 4),
+        w(10, 8),
+        w(15, 15),
+        w(25, 30),
+        w(0, 0),
+    ],
+    center_control: w(5, 5),
+    king_danger: king_danger_table(),
+    pawn_shield: [w(15, 0), w(8, 0)],
+    pawn_storm: [w(-5, 0), w(-20, -5), w(-12, 0), w(-5, 0)],
+    king_open_file: w(-25, -5),
+    king_semi_open_file: w(-12, 0),
+    knight_mobility: [
+        w(-30, -40),
+        w(-20, -28),
+        w(-6, -15),
+        w(-2, -8),
+        w(2, 3),
+        w(6, 6),
+        w(11, 9),
+        w(14, 10),
+        w(17, 12),
+    ],
+    bishop_mobility: [
+        w(-24, -30),
+        w(-10, -12),
+        w(8, -3),
+        w(13, 6),
+        w(19, 11),
+        w(26, 17),
+        w(28, 22),
+        w(31, 25),
+        w(34, 29),
+        w(37, 32),
+        w(40, 36),
+        w(43, 38),
+        w(45, 40),
+        w(48, 43),
+    ],
+    rook_mobility: [
+        w(-29, -29),
+        w(-11, -7),
+        w(-4, 11),
+        w(-2, 18),
+        w(1, 23),
+        w(6, 29),
+        w(8, 37),
+        w(13, 42),
+        w(20, 44),
+        w(20, 49),
+        w(23, 54),
+        w(26, 55),
+        w(29, 58),
+        w(31, 60),
+        w(34, 61),
+    ],
+    queen_mobility: [
+        w(-15, -24),
+        w(-8, -12),
+        w(-2, -4),
+        w(0, 2),
+        w(2, 6),
+        w(5, 10),
+        w(7, 14),
+        w(9, 17),
+        w(11, 20),
+        w(13, 23),
+        w(15, 26),
+        w(17, 28),
+        w(19, 30),
+        w(21, 32),
+        w(22, 34),
+        w(23, 36),
+        w(24, 38),
+        w(25, 40),
+        w(26, 41),
+        w(27, 42),
+        w(28, 43),
+        w(29, 44),
+        w(30, 45),
+        w(31, 46),
+        w(32, 47),
+        w(33, 48),
+        w(34, 49),
+        w(35, 50),
+    ],
+    threat_by_lesser: [w(0, 0), w(60, 40), w(60, 40), w(50, 40), w(60, 50), w(0, 0)],
+    hanging: w(35, 20),
+    pawn_push_threat: w(20, 12),
+    restricted: w(3, 3),
+    knight_outpost: w(30, 20),
+    bishop_outpost: w(20, 10),
+    bad_bishop: w(-3, -5),
+    knight_tropism: w(3, 0),
+    trapped_bishop: w(-100, -80),
+    trapped_rook: w(-50, -10),
+    castling_both: w(20, 20),
+    castling_one: w(10, 10),
+    pawn_mg: [
+        [0, 0, 0, 0, 0, 0, 0, 0],
+        [50, 50, 50, 50, 50, 50, 50, 50],
+        [10, 10, 20, 30, 30, 20, 10, 10],
+        [5, 5, 10, 25, 25, 10, 5, 5],
+        [0, 0, 0, 20, 20, 0, 0, 0],
+        [5, -5, -10, 0, 0, -10, -5, 5],
+        [5, 10, 10, -20, -20, 10, 10, 5],
+        [0, 0, 0, 0, 0, 0, 0, 0],
+    ],
+    pawn_eg: [
+        [0, 0, 0, 0, 0, 0, 0, 0],
+        [50, 50, 50, 50, 50, 50, 50, 50],
+        [10, 10, 20, 30, 30, 20, 10, 10],
+        [5, 5, 10, 25, 25, 10, 5, 5],
+        [0, 0, 0, 20, 20, 0, 0, 0],
+        [5, -5, -10, 0, 0, -10, -5, 5],
+        [5, 10, 10, -20, -20, 10, 10, 5],
+        [0, 0, 0, 0, 0, 0, 0, 0],
+    ],
+    knight_mg: [
+        [-50, -40, -30, -30, -30, -30, -40, -50],
+        [-40, -20, 0, 0, 0, 0, -20, -40],
+        [-30, 0, 10, 15, 15, 10, 0, -30],
+        [-30, 5, 15, 20, 20, 15, 5, -30],
+        [-30, 0, 15, 20, 20, 15, 0, -30],
+        [-30, 5, 10, 15, 15, 10, 5, -30],
+        [-40, -20, 0, 5, 5, 0, -20, -40],
+        [-50, -40, -30, -30, -30, -30, -40, -50],
+    ],
+    knight_eg: [
+        [-50, -40, -30, -30, -30, -30, -40, -50],
+        [-40, -20, 0, 0, 0, 0, -20, -40],
+        [-30, 0, 10, 15, 15, 10, 0, -30],
+        [-30, 5, 15, 20, 20, 15, 5, -30],
+        [-30, 0, 15, 20, 20, 15, 0, -30],
+        [-30, 5, 10, 15, 15, 10, 5, -30],
+        [-40, -20, 0, 5, 5, 0, -20, -40],
+        [-50, -40, -30, -30, -30, -30, -40, -50],
+    ],
+    bishop_mg: [
+        [-20, -10, -10, -10, -10, -10, -10, -20],
+        [-10, 0, 0, 0, 0, 0, 0, -10],
+        [-10, 0, 5, 10, 10, 5, 0, -10],
+        [-10, 5, 5, 10, 10, 5, 5, -10],
+        [-10, 0, 10, 10, 10, 10, 0, -10],
+        [-10, 10, 10, 10, 10, 10, 10, -10],
+        [-10, 5, 0, 0, 0, 0, 5, -10],
+        [-20, -10, -10, -10, -10, -10, -10, -20],
+    ],
+    bishop_eg: [
+        [-20, -10, -10, -10, -10, -10, -10, -20],
+        [-10, 0, 0, 0, 0, 0, 0, -10],
+        [-10, 0, 5, 10, 10, 5, 0, -10],
+        [-10, 5, 5, 10, 10, 5, 5, -10],
+        [-10, 0, 10, 10, 10, 10, 0, -10],
+        [-10, 10, 10, 10, 10, 10, 10, -10],
+        [-10, 5, 0, 0, 0, 0, 5, -10],
+        [-20, -10, -10, -10, -10, -10, -10, -20],
+    ],
+    rook_mg: [
+        [0, 0, 0, 0, 0, 0, 0, 0],
+        [5, 10, 10, 10, 10, 10, 10, 5],
+        [-5, 0, 0, 0, 0, 0, 0, -5],
+        [-5, 0, 0, 0, 0, 0, 0, -5],
+        [-5, 0, 0, 0, 0, 0, 0, -5],
+        [-5, 0, 0, 0, 0, 0, 0, -5],
+        [-5, 0, 0, 0, 0, 0, 0, -5],
+        [0, 0, 0, 5, 5, 0, 0, 0],
+    ],
+    rook_eg: [
+        [0, 0, 0, 0, 0, 0, 0, 0],
+        [5, 10, 10, 10, 10, 10, 10, 5],
+        [-5, 0, 0, 0, 0, 0, 0, -5],
+        [-5, 0, 0, 0, 0, 0, 0, -5],
+        [-5, 0, 0, 0, 0, 0, 0, -5],
+        [-5, 0, 0, 0, 0, 0, 0, -5],
+        [-5, 0, 0, 0, 0, 0, 0, -5],
+        [0, 0, 0, 5, 5, 0, 0, 0],
+    ],
+    queen_mg: [
+        [-20, -10, -10, -5, -5, -10, -10, -20],
+        [-10, 0, 0, 0, 0, 0, 0, -10],
+        [-10, 0, 5, 5, 5, 5, 0, -10],
+        [-5, 0, 5, 5, 5, 5, 0, -5],
+        [0, 0, 5, 5, 5, 5, 0, -5],
+        [-10, 5, 5, 5, 5, 5, 0, -10],
+        [-10, 0, 5, 0, 0, 0, 0, -10],
+        [-20, -10, -10, -5, -5, -10, -10, -20],
+    ],
+    queen_eg: [
+        [-20, -10, -10, -5, -5, -10, -10, -20],
+        [-10, 0, 0, 0, 0, 0, 0, -10],
+        [-10, 0, 5, 5, 5, 5, 0, -10],
+        [-5, 0, 5, 5, 5, 5, 0, -5],
+        [0, 0, 5, 5, 5, 5, 0, -5],
+        [-10, 5, 5, 5, 5, 5, 0, -10],
+        [-10, 0, 5, 0, 0, 0, 0, -10],
+        [-20, -10, -10, -5, -5, -10, -10, -20],
+    ],
+    king_mg: [
+        [-30, -40, -40, -50, -50, -40, -40, -30],
+        [-30, -40, -40, -50, -50, -40, -40, -30],
+        [-30, -40, -40, -50, -50, -40, -40, -30],
+        [-30, -40, -40, -50, -50, -40, -40, -30],
+        [-20, -30, -30, -40, -40, -30, -30, -20],
+        [-10, -20, -20, -20, -20, -20, -20, -10],
+        [20, 20, 0, 0, 0, 0, 20, 20],
+        [20, 30, 10, 0, 0, 10, 30, 20],
+    ],
+    king_eg: [
+        [-50, -40, -30, -20, -20, -30, -40, -50],
+        [-30, -20, -10, 0, 0, -10, -20, -30],
+        [-30, -10, 20, 30, 30, 20, -10, -30],
+        [-30, -10, 30, 40, 40, 30, -10, -30],
+        [-30, -10, 30, 40, 40, 30, -10, -30],
+        [-30, -10, 20, 30, 30, 20, -10, -30],
+        [-30, -30, 0, 0, 0, 0, -30, -30],
+        [-50, -30, -30, -30, -30, -30, -30, -50],
+    ],
+};
+
+// grows with the square of the attack units up to five pawns; the endgame value is a quarter
+// of the middlegame one, so the term fades out as the pieces come off
+const fn king_danger_table() -> [Weight; MAX_ATTACK_UNITS] {
+    let mut table = [w(0, 0); MAX_ATTACK_UNITS];
+    let mut units = 0;
+    while units < MAX_ATTACK_UNITS {
+        let mut value = (units * units * 5 / 28) as i32;
+        if value > 500 {
+            value = 500;
+        }
+        table[units] = w(-value, -value / 4);
+        units += 1;
+    }
+    table
+}
+
+impl EvalParams {
+    #[inline]
+    pub(crate) fn weight(&self, term: Term) -> Weight {
+        match term {
+            Term::Material(piece) => match piece {
+                Piece::Pawn => self.pawn,
+                Piece::Knight => self.knight,
+                Piece::Bishop => self.bishop,
+                Piece::Rook => self.rook,
+                Piece::Queen => self.queen,
+                Piece::King => Weight::default(),
+            },
+            Term::PieceSquare(piece, i) => {
+                let (mg, eg) = self.piece_square_tables(piece);
+                w(mg[i / 8][i % 8], eg[i / 8][i % 8])
+            }
+            Term::BishopPair => self.bishop_pair,
+            Term::RookOnOpenFile => self.rook_on_open_file,
+            Term::RookOnSemiOpenFile => self.rook_on_semi_open_file,
+            Term::RookOn7th => self.rook_on_7th,
+            Term::RookOn8th => self.rook_on_8th,
+            Term::IsolatedPawn => self.isolated_pawn,
+            Term::DoubledPawn => self.doubled_pawn,
+            Term::BackwardPawn => self.backward_pawn,
+            Term::PassedPawn(rank) => self.passed_pawn[rank],
+            Term::CandidatePassedPawn(rank) => self.candidate_passed_pawn[rank],
+            Term::ConnectedPawn(rank) => self.connected_pawn[rank],
+            Term::PhalanxPawn(rank) => self.phalanx_pawn[rank],
+            Term::CenterControl => self.center_control,
+            Term::KingDanger(units) => self.king_danger[units],
+            Term::PawnShield(i) => self.pawn_shield[i],
+            Term::PawnStorm(i) => self.pawn_storm[i],
+            Term::KingOpenFile => self.king_open_file,
+            Term::KingSemiOpenFile => self.king_semi_open_file,
+            Term::Mobility(piece, count) => match piece {
+                Piece::Knight => self.knight_mobility[count],
+                Piece::Bishop => self.bishop_mobility[count],
+                Piece::Rook => self.rook_mobility[count],
+                _ => self.queen_mobility[count],
+            },
+            Term::ThreatByLesser(piece) => self.threat_by_lesser[piece.to_index()],
+            Term::Hanging => self.hanging,
+            Term::PawnPushThreat => self.pawn_push_threat,
+            Term::Restricted => self.restricted,
+            Term::Outpost(Piece::Knight) => self.knight_outpost,
+            Term::Outpost(_) => self.bishop_outpost,
+            Term::BadBishop => self.bad_bishop,
+            Term::KnightTropism => self.knight_tropism,
+            Term::TrappedBishop => self.trapped_bishop,
+            Term::TrappedRook => self.trapped_rook,
+            Term::CastlingBoth => self.castling_both,
+            Term::CastlingOne => self.castling_one,
+        }
+    }
+
+    fn piece_square_tables(&self, piece: Piece) -> (&Table, &Table) {
+        match piece {
+            Piece::Pawn => (&self.pawn_mg, &self.pawn_eg),
+            Piece::Knight => (&self.knight_mg, &self.knight_eg),
+            Piece::Bishop => (&self.bishop_mg, &self.bishop_eg),
+            Piece::Rook => (&self.rook_mg, &self.rook_eg),
+            Piece::Queen => (&self.queen_mg, &self.queen_eg),
+            Piece::King => (&self.king_mg, &self.king_eg),
+        }
+    }
+
+    // every entry of the weights file, in file order; the tuner indexes weights by it
+    pub(crate) fn entries(&mut self) -> Vec<(&'static str, Entry<'_>)> {
+        use Phase::*;
+        vec![
+            ("pawn", Entry::Weights(std::slice::from_mut(&mut self.pawn))),
+            (
+                "knight",
+                Entry::Weights(std::slice::from_mut(&mut self.knight)),
+            ),
+            (
+                "bishop",
+                Entry::Weights(std::slice::from_mut(&mut self.bishop)),
+            ),
+            ("rook", Entry::Weights(std::slice::from_mut(&mut self.rook))),
+            (
+                "queen",
+                Entry::Weights(std::slice::from_mut(&mut self.queen)),
+            ),
+            (
+                "bishop_pair",
+                Entry::Weights(std::slice::from_mut(&mut self.bishop_pair)),
+            ),
+            (
+                "rook_on_open_file",
+                Entry::Weights(std::slice::from_mut(&mut self.rook_on_open_file)),
+            ),
+            (
+                "rook_on_semi_open_file",
+                Entry::Weights(std::slice::from_mut(&mut self.rook_on_semi_open_file)),
+            ),
+            (
+                "rook_on_7th",
+                Entry::Weights(std::slice::from_mut(&mut self.rook_on_7th)),
+            ),
+            (
+                "rook_on_8th",
+                Entry::Weights(std::slice::from_mut(&mut self.rook_on_8th)),
+            ),
+            (
+                "isolated_pawn",
+                Entry::Weights(std::slice::from_mut(&mut self.isolated_pawn)),
+            ),
+            (
+                "doubled_pawn",
+                Entry::Weights(std::slice::from_mut(&mut self.doubled_pawn)),
+            ),
+            (
+                "backward_pawn",
+                Entry::Weights(std::slice::from_mut(&mut self.backward_pawn)),
+            ),
+            ("passed_pawn", Entry::Weights(&mut self.passed_pawn)),
+            (
+                "candidate_passed_pawn",
+                Entry::Weights(&mut self.candidate_passed_pawn),
+            ),
+            ("connected_pawn", Entry::Weights(&mut self.connected_pawn)),
+            ("phalanx_pawn", Entry::Weights(&mut self.phalanx_pawn)),
+            (
+                "center_control",
+                Entry::Weights(std::slice::from_mut(&mut self.center_control)),
+            ),
+            ("king_danger", Entry::Weights(&mut self.king_danger)),
+            ("pawn_shield", Entry::Weights(&mut self.pawn_shield)),
+            ("pawn_storm", Entry::Weights(&mut self.pawn_storm)),
+            (
+                "king_open_file",
+                Entry::Weights(std::slice::from_mut(&mut self.king_open_file)),
+            ),
+            (
+                "king_semi_open_file",
+                Entry::Weights(std::slice::from_mut(&mut self.king_semi_open_file)),
+            ),
+            ("knight_mobility", Entry::Weights(&mut self.knight_mobility)),
+            ("bishop_mobility", Entry::Weights(&mut self.bishop_mobility)),
+            ("rook_mobility", Entry::Weights(&mut self.rook_mobility)),
+            ("queen_mobility", Entry::Weights(&mut self.queen_mobility)),
+            (
+                "threat_by_lesser",
+                Entry::Weights(&mut self.threat_by_lesser),
+            ),
+            (
+                "hanging",
+                Entry::Weights(std::slice::from_mut(&mut self.hanging)),
+            ),
+            (
+                "pawn_push_threat",
+                Entry::Weights(std::slice::from_mut(&mut self.pawn_push_threat)),
+            ),
+            (
+                "restricted",
+                Entry::Weights(std::slice::from_mut(&mut self.restricted)),
+            ),
+            (
+                "knight_outpost",
+                Entry::Weights(std::slice::from_mut(&mut self.knight_outpost)),
+            ),
+            (
+                "bishop_outpost",
+                Entry::Weights(std::slice::from_mut(&mut self.bishop_outpost)),
+            ),
+            (
+                "bad_bishop",
+                Entry::Weights(std::slice::from_mut(&mut self.bad_bishop)),
+            ),
+            (
+                "knight_tropism",
+                Entry::Weights(std::slice::from_mut(&mut self.knight_tropism)),
+            ),
+            (
+                "trapped_bishop",
+                Entry::Weights(std::slice::from_mut(&mut self.trapped_bishop)),
+            ),
+            (
+                "trapped_rook",
+                Entry::Weights(std::slice::from_mut(&mut self.trapped_rook)),
+            ),
+            (
+                "castling_both",
+                Entry::Weights(std::slice::from_mut(&mut self.castling_both)),
+            ),
+            (
+                "castling_one",
+                Entry::Weights(std::slice::from_mut(&mut self.castling_one)),
+            ),
+            ("pawn_mg", Entry::Table(&mut self.pawn_mg, Middlegame)),
+            ("pawn_eg", Entry::Table(&mut self.pawn_eg, Endgame)),
+            ("knight_mg", Entry::Table(&mut self.knight_mg, Middlegame)),
+            ("knight_eg", Entry::Table(&mut self.knight_eg, Endgame)),
+            ("bishop_mg", Entry::Table(&mut self.bishop_mg, Middlegame)),
+            ("bishop_eg", Entry::Table(&mut self.bishop_eg, Endgame)),
+            ("rook_mg", Entry::Table(&mut self.rook_mg, Middlegame)),
+            ("rook_eg", Entry::Table(&mut self.rook_eg, Endgame)),
+            ("queen_mg", Entry::Table(&mut self.queen_mg, Middlegame)),
+            ("queen_eg", Entry::Table(&mut self.queen_eg, Endgame)),
+            ("king_mg", Entry::Table(&mut self.king_mg, Middlegame)),
+            ("king_eg", Entry::Table(&mut self.king_eg, Endgame)),
+        ]
+    }
+
+    // the weights and a warning for every line that was skipped
+    pub fn load(path: &str) -> Result<(EvalParams, Vec<String>), String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        let (params, warnings) =
+            EvalParams::from_text(&text).map_err(|e| format!("{}: {}", path, e))?;
+        let warnings = warnings
+            .into_iter()
+            .map(|warning| format!("{}: {}", path, warning))
+            .collect();
+        Ok((params, warnings))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_text()).map_err(|e| format!("cannot write {}: {}", path, e))
+    }
+
+    // weights missing from the text keep their default value; retired weights are skipped
+    // with a warning
+    pub fn from_text(text: &str) -> Result<(EvalParams, Vec<String>), String> {
+        let mut params = DEFAULT_PARAMS;
+        let mut warnings = Vec::new();
+        // comments run from '#' to the end of the line; lists may span several lines
+        let text: String = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or(""))
+            .collect::<Vec<&str>>()
+            .join("\n");
+        let mut rest = text.trim();
+        while !rest.is_empty() {
+            let (name, after) = rest
+                .split_once('=')
+                .ok_or_else(|| format!("expected 'name = value' at '{}'", first_line(rest)))?;
+            let name = name.trim();
+            let after = after.trim_start();
+            let (value, remainder) = if let Some(list) = after.strip_prefix('[') {
+                list.split_once(']')
+                    .ok_or_else(|| format!("unterminated list '{}'", name))?
+            } else {
+                after.split_once('\n').unwrap_or((after, ""))
+            };
+            if RETIRED.contains(&name) {
+                warnings.push(format!("ignoring retired weight '{}'", name));
+            } else {
+                params.set(name, value)?;
+            }
+            rest = remainder.trim();
+        }
+        Ok((params, warnings))
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let mut entries = self.entries();
+        let Some((_, entry)) = entries.iter_mut().find(|(n, _)| *n == name) else {
+            return Err(format!("unknown weight '{}'", name));
+        };
+        let mut values = Vec::new();
+        for v in value
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|v| !v.is_empty())
+        {
+            values.push(
+                v.parse::<i32>()
+                    .map_err(|_| format!("invalid value '{}' for '{}'", v, name))?,
+            );
+        }
+        // a single number sets both phases of every weight of the entry, which also reads the
+        // one-value-per-name files written before weights had phases
+        if let (&[value], Entry::Weights(_)) = (&values[..], &entry) {
+            values = vec![value; entry.len()];
+        }
+        if values.len() != entry.len() {
+            return Err(format!(
+                "'{}' needs {} values, found {}",
+                name,
+                entry.len(),
+                values.len()
+            ));
+        }
+        for (i, v) in values.into_iter().enumerate() {
+            entry.set(i, v);
+        }
+        Ok(())
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out =
+            String::from("# rusty evaluation weights: [middlegame, endgame] per weight\n");
+        let mut params = self.clone();
+        for (name, entry) in params.entries() {
+            let values: Vec<String> = (0..entry.len())
+                .map(|i| format!("{:4}", entry.get(i)))
+                .collect();
+            match entry {
+                Entry::Weights(weights) if weights.len() == 1 => {
+                    writeln!(out, "{} = [{}, {}]", name, weights[0].mg, weights[0].eg).unwrap();
+                }
+                Entry::Weights(_) => {
+                    writeln!(out, "\n{} = [", name).unwrap();
+                    for pair in values.chunks(2) {
+                        writeln!(out, "   {},", pair.join(",")).unwrap();
+                    }
+                    writeln!(out, "]").unwrap();
+                }
+                Entry::Table(..) => {
+                    writeln!(out, "\n{} = [", name).unwrap();
+                    for row in values.chunks(8) {
+                        writeln!(out, "   {},", row.join(",")).unwrap();
+                    }
+                    writeln!(out, "]").unwrap();
+                }
+            }
+        }
+        out
+    }
+}
+
+impl Default for EvalParams {
+    fn default() -> EvalParams {
+        DEFAULT_PARAMS
+    }
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut params = EvalParams {
+            bishop_pair: w(41, 57),
+            ..DEFAULT_PARAMS
+        };
+        params.passed_pawn[5] = w(-3, 120);
+        params.knight_mg[2][3] = 17;
+        let path = std::env::temp_dir().join(format!("rusty-weights-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        params.save(path).unwrap();
+        let loaded = EvalParams::load(path);
+        std::fs::remove_file(path).ok();
+        assert_eq!(loaded, Ok((params, Vec::new())));
+    }
+
+    #[test]
+    fn missing_weights_keep_their_default() {
+        let (params, _) = EvalParams::from_text("# only one\nbishop_pair = [40, 60]\n").unwrap();
+        assert_eq!(params.bishop_pair, w(40, 60));
+        assert_eq!(params.pawn, DEFAULT_PARAMS.pawn);
+    }
+
+    #[test]
+    fn reads_single_values_for_both_phases() {
+        let (params, _) =
+            EvalParams::from_text("pawn = 90\nbishop_pair = 30\npassed_pawn = 20\n").unwrap();
+        assert_eq!(params.pawn, w(90, 90));
+        assert_eq!(params.bishop_pair, w(30, 30));
+        assert_eq!(params.passed_pawn, [w(20, 20); 8]);
+        // a table still needs all of its values
+        assert!(EvalParams::from_text("pawn_mg = 5").is_err());
+    }
+
+    #[test]
+    fn skips_retired_weights() {
+        let (params, warnings) =
+            EvalParams::from_text("king_safety = 5\npawn_on_7th = [20, 30]\npawn = 90\n").unwrap();
+        assert_eq!(params.pawn, w(90, 90));
+        assert_eq!(
+            warnings,
+            [
+                "ignoring retired weight 'king_safety'",
+                "ignoring retired weight 'pawn_on_7th'"
+            ]
+        );
+        // the king and queen weights the evaluation never read are retired too
+        let text = "king = [20000, 20000]\nqueen_on_7th = [10, 10]\nqueen_on_8th = 20\n";
+        assert_eq!(EvalParams::from_text(text).unwrap().0, DEFAULT_PARAMS);
+        let saved = DEFAULT_PARAMS.to_text();
+        assert!(!saved.contains("queen_on_7th") && !saved.contains("\nking ="));
+    }
+
+    #[test]
+    fn rejects_unknown_and_malformed_lines() {
+        assert_eq!(
+            EvalParams::from_text("no_such_weight = [1, 2]"),
+            Err(String::from("unknown weight 'no_such_weight'"))
+        );
+        for text in [
+            "bishop_pair [1, 2]",
+            "bishop_pair = [1, x]",
+            "bishop_pair = [1, 2, 3]",
+            "passed_pawn = [1, 2",
+            "pawn_mg = [1, 2]",
+            "= [1, 2]",
+        ] {
+            assert!(EvalParams::from_text(text).is_err(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use rusty::chess::{ChessMove, Color};
+use rusty::SearchObserver;
+use rusty::{outcome, Engine, EngineConfig, IterationInfo, Position, Score, SearchLimits};
+use std::str::FromStr;
+use std::time::Duration;
+
+// the WinBoard/XBoard protocol (CECP), version 2
+
+const FEATURES: &str = "feature myname=\"Rusty\" setboard=1 usermove=1 ping=1 playother=1 \
+     san=0 time=1 draw=0 sigint=0 sigterm=0 reuse=1 analyze=0 colors=0 done=1";
+
+// moves assumed left in the game when the time control does not say
+const MOVES_TO_GO: u32 = 30;
+
+struct TimeControl {
+    // moves per session, 0 for the whole game
+    moves: u32,
+    base: Duration,
+    increment: Duration,
+    // st: a fixed time for every move
+    per_move: Option<Duration>,
+    // sd: the deepest iteration
+    depth: Option<u8>,
+}
+
+struct Xboard {
+    engine: Engine,
+    // every position of the game so far, the current one last
+    history: Vec<Position>,
+    // the side the engine plays, None in force mode
+    engine_color: Option<Color>,
+    post: bool,
+    control: TimeControl,
+    // the engine's and the opponent's clocks as last reported
+    time: Option<Duration>,
+    opponent_time: Option<Duration>,
+}
+
+impl Xboard {
+    fn new(config: EngineConfig) -> Xboard {
+        Xboard {
+            engine: Engine::new(config),
+            history: vec![Position::default()],
+            engine_color: Some(Color::Black),
+            post: false,
+            control: TimeControl {
+                moves: 0,
+                base: Duration::from_secs(300),
+                increment: Duration::ZERO,
+                per_move: None,
+                depth: None,
+            },
+            time: None,
+            opponent_time: None,
+        }
+    }
+
+    fn position(&self) -> &Position {
+        self.history.last().unwrap()
+    }
+
+    // false once the GUI has asked to quit
+    fn handle(&mut self, line: &str) -> bool {
+        let mut args = line.split_whitespace();
+        let command = args.next().unwrap_or("");
+        let rest: Vec<&str> = args.collect();
+        match command {
+            "protover" => println!("{}", FEATURES),
+            "ping" => println!("pong {}", rest.first().unwrap_or(&"")),
+            "new" => self.new_game(),
+            "force" | "result" => self.engine_color = None,
+            "go" => {
+                self.engine_color = Some(self.position().board.side_to_move());
+                self.think();
+            }
+            "playother" => self.engine_color = Some(!self.position().board.side_to_move()),
+            "usermove" => self.user_move(rest.first().unwrap_or(&"")),
+            "level" => match parse_level(&rest) {
+                Some((moves, base, increment)) => {
+                    self.control.moves = moves;
+                    self.control.base = base;
+                    self.control.increment = increment;
+                    self.control.per_move = None;
+                }
+                None => println!("Error (bad level): {}", line.trim()),
+            },
+            "st" => match rest.first().and_then(|s| s.parse::<f64>().ok()) {
+                Some(seconds) if seconds > 0.0 => {
+                    self.control.per_move = Some(Duration::from_secs_f64(seconds))
+                }
+                _ => println!("Error (bad st): {}", line.trim()),
+            },
+            "sd" => match rest.first().and_then(|s| s.parse::<u8>().ok()) {
+                Some(depth) if depth > 0 => self.control.depth = Some(depth),
+                _ => println!("Error (bad sd): {}", line.trim()),
+            },
+            "time" => self.time = parse_centiseconds(&rest),
+            "otim" => self.opponent_time = parse_centiseconds(&rest),
+            "undo" => self.take_back(1),
+            "remove" => self.take_back(2),
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "setboard" => self.set_board(&rest.join(" ")),
+            "quit" => return false,
+            // xboard, accepted, rejected, random, hard, easy, computer and the like need no
+            // answer
+            _ => {}
+        }
+        true
+    }
+
+    fn new_game(&mut self) {
+        self.history = vec![Position::default()];
+        self.engine_color = Some(Color::Black);
+        self.control.depth = None;
+        self.time = None;
+        self.opponent_time = None;
+    }
+
+    fn set_board(&mut self, fen: &str) {
+        match Position::from_fen(fen) {
+            Ok(position) => self.history = vec![position],
+            Err(error) => println!("tellusererror Illegal position: {}", error),
+        }
+    }
+
+    fn take_back(&mut self, plies: usize) {
+        if self.history.len() <= plies {
+            println!(
+                "Error (cannot undo): {}",
+                if plies == 1 { "undo" } else { "remove" }
+            );
+            return;
+        }
+        self.history.truncate(self.history.len() - plies);
+    }
+
+    fn user_move(&mut self, text: &str) {
+        let m = match ChessMove::from_str(text) {
+            Ok(m) if self.position().board.legal(m) => m,
+            _ => {
+                println!("Illegal move: {}", text);
+                return;
+            }
+        };
+        self.play(m);
+        if self.result().is_none()
+            && self.engine_color == Some(self.position().board.side_to_move())
+        {
+            self.think();
+        }
+    }
+
+    fn play(&mut self, m: ChessMove) {
+        let next = self.position().make_move_new(m);
+        self.history.push(next);
+        if let Some(result) = self.result() {
+            println!("{}", result);
+        }
+    }
+
+    // the result line if the game has ended
+    fn result(&self) -> Option<String> {
+        outcome(&self.history).map(|o| format!("{} {{{}}}", o.result(), o.reason()))
+    }
+
+    // how long to think about the next move
+    fn move_time(&self) -> Duration {
+        if let Some(per_move) = self.control.per_move {
+            return per_move;
+        }
+        let clock = self.time.unwrap_or(self.control.base);
+        let played = (self.position().fullmove_number - 1) % self.control.moves.max(1);
+        let moves_to_go = if self.control.moves > 0 {
+            self.control.moves - played
+        } else {
+            MOVES_TO_GO
+        };
+        // keep a little in reserve for the GUI's overhead
+        let budget = clock / moves_to_go + self.control.increment;
+        budget.min(clock.saturating_sub(Duration::from_millis(50)))
+    }
+
+    fn think(&mut self) {
+        if self.result().is_some() {
+            return;
+        }
+        self.engine.set_position(*self.position());
+        let limits = SearchLimits {
+            depth: self.control.depth,
+            movetime: Some(self.move_time()),
+            ..Default::default()
+        };
+        let mut thinking = Thinking { post: self.post };
+        let result = self.engine.search_with(limits, &mut thinking);
+        if let Some(m) = result.best_move {
+            println!("move {}", m);
+            self.play(m);
+        }
+    }
+}
+
+// post: one line per iteration, "ply score time nodes pv" with the time in centiseconds
+struct Thinking {
+    post: bool,
+}
+
+impl SearchObserver for Thinking {
+    fn iteration(&mut self, info: &IterationInfo) {
+        if !self.post {
+            return;
+        }
+        let pv: Vec<String> = info.pv.iter().map(|m| m.to_string()).collect();
+        println!(
+            "{} {} {} {} {}",
+            info.depth,
+            xboard_score(info.score),
+            info.elapsed.as_millis() / 10,
+            info.nodes,
+            pv.join(" ")
+        );
+    }
+}
+
+// mates as 100000 plus the distance in moves, the convention GUIs recognise
+fn xboard_score(score: Score) -> i32 {
+    match score.mate_in() {
+        Some(moves) if moves > 0 => 100_000 + moves,
+        Some(moves) => -100_000 + moves,
+        None => score.centipawns(),
+    }
+}
+
+// "level 40 5 0", "level 0 2:30 1.5": moves per session, base in minutes, increment in seconds
+fn parse_level(args: &[&str]) -> Option<(u32, Duration, Duration)> {
+    let [moves, base, increment] = args else {
+        return None;
+    };
+    let base = match base.split_once(':') {
+        Some((minutes, seconds)) => {
+            minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok()?
+        }
+        None => base.parse::<u64>().ok()? * 60,
+    };
+    let increment = increment.parse::<f64>().ok().filter(|i| *i >= 0.0)?;
+    Some((
+        moves.parse().ok()?,
+        Duration::from_secs(base),
+        Duration::from_secs_f64(increment),
+    ))
+}
+
+fn parse_centiseconds(args: &[&str]) -> Option<Duration> {
+    let centiseconds = args.first()?.parse::<u64>().ok()?;
+    Some(Duration::from_millis(centiseconds * 10))
+}
+
+// `first` is the "xboard" line the protocol was chosen by
+pub(crate) fn main(config: EngineConfig, first: &str) {
+    let mut xboard = Xboard::new(config);
+    if !xboard.handle(first) {
+        return;
+    }
+    let mut line = String::new();
+    while matches!(std::io::stdin().read_line(&mut line), Ok(n) if n > 0) {
+        if !xboard.handle(&line) {
+            return;
+        }
+        line.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xboard() -> Xboard {
+        let mut xboard = Xboard::new(EngineConfig::default());
+        xboard.handle("xboard");
+        xboard.handle("protover 2");
+        xboard
+    }
+
+    #[test]
+    fn level_formats() {
+        assert_eq!(
+            parse_level(&["40", "5", "0"]),
+            Some((40, Duration::from_secs(300), Duration::ZERO))
+        );
+        assert_eq!(
+            parse_level(&["0", "2:30", "1.5"]),
+            Some((0, Duration::from_secs(150), Duration::from_millis(1500)))
+        );
+        assert_eq!(parse_level(&["0", "x", "0"]), None);
+        assert_eq!(parse_level(&["0", "5"]), None);
+    }
+
+    #[test]
+    fn force_mode_moves_and_takebacks() {
+        let mut xboard = xboard();
+        xboard.handle("new");
+        xboard.handle("force");
+        for m in ["e2e4", "e7e5", "g1f3"] {
+            xboard.handle(&format!("usermove {}", m));
+        }
+        assert_eq!(xboard.history.len(), 4);
+        // an illegal move changes nothing
+        xboard.handle("usermove e5e3");
+        assert_eq!(xboard.history.len(), 4);
+        xboard.handle("undo");
+        assert_eq!(xboard.position().board.side_to_move(), Color::White);
+        xboard.handle("remove");
+        assert_eq!(xboard.history.len(), 1);
+        xboard.handle("remove");
+        assert_eq!(xboard.history.len(), 1);
+    }
+
+    #[test]
+    fn engine_answers_a_user_move() {
+        let mut xboard = xboard();
+        xboard.handle("new");
+        xboard.handle("sd 2");
+        xboard.handle("usermove e2e4");
+        // the engine plays black and replies at once
+        assert_eq!(xboard.history.len(), 3);
+        assert_eq!(xboard.position().board.side_to_move(), Color::White);
+    }
+
+    #[test]
+    fn setboard_and_game_end() {
+        let mut xboard = xboard();
+        xboard.handle("force");
+        xboard.handle("setboard 6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1");
+        assert_eq!(xboard.result(), None);
+        xboard.handle("sd 2");
+        xboard.handle("go");
+        assert_eq!(xboard.result().as_deref(), Some("1-0 {White mates}"));
+        // a malformed position keeps the game as it was
+        xboard.handle("setboard not a fen");
+        assert_eq!(xboard.result().as_deref(), Some("1-0 {White mates}"));
+    }
+
+    #[test]
+    fn depth_limit_keeps_to_the_clock() {
+        let mut xboard = xboard();
+        xboard.handle("new");
+        xboard.handle("force");
+        xboard.handle("sd 20");
+        xboard.handle("level 40 5 0");
+        // one second left for the 40 moves
+        xboard.handle("time 100");
+        assert_eq!(xboard.move_time(), Duration::from_millis(25));
+        let start = std::time::Instant::now();
+        xboard.handle("go");
+        assert_eq!(xboard.history.len(), 2);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn time_budget() {
+        let mut xboard = xboard();
+        xboard.handle("level 40 5 0");
+        xboard.handle("time 6000");
+        // 60 seconds for the 40 moves left
+        assert_eq!(xboard.move_time(), Duration::from_millis(1500));
+        xboard.handle("st 2");
+        assert_eq!(xboard.move_time(), Duration::from_secs(2));
+        assert_eq!(xboard_score(Score::mate_in_plies(3)), 100_002);
+        assert_eq!(xboard_score(Score::mated_in_plies(2)), -100_001);
+    }
+}